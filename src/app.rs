@@ -1,9 +1,16 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+/// How long a status message stays on screen
+const STATUS_TIMEOUT: Duration = Duration::from_secs(8);
+
 use crate::browser::BrowserState;
 
+use crate::equalizer::EqualizerState;
+
+use crate::list::ListSelector;
+
 use crate::library::{
     LibraryState,
     LibraryTrack
@@ -11,19 +18,54 @@ use crate::library::{
 
 use crate::persistence;
 
-use crate::player::Player;
+use crate::player::{
+    FadeCurve, MAX_CROSSFADE_SECS, MAX_SPEED, MIN_SPEED, OutputDevice, Player, PlayerCommand,
+    PlayerEvent, PlayerEvents, PlayerHandle, SinkConfig, StageSlot, device_available,
+    list_output_devices, normalize_slots,
+};
+
+use crate::settings::Settings;
+
+use crate::loudness::LoudnessScan;
+
+use crate::meters::MeterState;
+
+use crate::visualizer::SpectrumState;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AppScreen {
     Library,
     Browser,
+    Equalizer,
+    Dsp,
+    Output,
+    Visualizer,
+    Lyrics,
 }
 
 pub struct App {
     pub screen: AppScreen,
     pub browser: BrowserState,
+    pub equalizer: EqualizerState,
+
+    /// DSP chain as shown on the DSP screen, kept in sync with the player
+    pub dsp: ListSelector<StageSlot>,
+
+    /// Output devices on the output screen; `None` is the system default
+    pub devices: ListSelector<Option<OutputDevice>>,
+
+    /// Analysed output for the visualizer screen, only updated while shown
+    pub spectrum: SpectrumState,
+
+    /// Output levels for the footer
+    pub meters: MeterState,
+
+    /// Loudness analysis running in the background, if any
+    loudness_scan: Option<LoudnessScan>,
+
     pub library: Arc<Mutex<LibraryState>>,
-    pub player: Arc<Mutex<Player>>,
+    pub player: PlayerHandle,
+    events: PlayerEvents,
     pub play_queue: Vec<PathBuf>,
     pub queue_index: usize,
     pub autoplay_enabled: bool,
@@ -32,11 +74,26 @@ pub struct App {
     /// Playback duration in seconds
     pub playback_duration: u64,
 
-    /// Playback start
-    pub playback_start: Option<Instant>,
+    /// Position in the current track, as last reported by the player
+    position: Duration,
+
+    /// Unplayable tracks autoplay may still skip before giving up
+    skips_left: usize,
 
-    pub paused_at: Option<Instant>,
-    pub paused_duration: Duration,
+    /// A-B loop in the current track: A once it is marked, then the whole
+    /// region once B is
+    pub loop_a: Option<Duration>,
+    pub loop_region: Option<(Duration, Duration)>,
+
+    /// First line shown of unsynced lyrics; synced ones scroll on their own
+    pub lyrics_scroll: usize,
+
+    pub settings: Settings,
+
+    /// One-line message shown above the footer, e.g. a missing device or a
+    /// track that could not be played, and when it was set
+    pub status: Option<String>,
+    status_at: Option<Instant>,
 }
 
 impl App {
@@ -47,108 +104,748 @@ impl App {
         library.lock().unwrap().artists = artists;
         library.lock().unwrap().rebuild_visible_rows(); // Make sure UI stays in sync
 
+        let settings = persistence::load_settings().unwrap_or_else(|err| {
+            log::warn!("Could not load settings, using defaults: {err}");
+            Settings::default()
+        });
+
+        let mut status = None;
+        if let Some(device) = &settings.output_device
+            && settings.sink == SinkConfig::Cpal
+            && !device_available(device)
+        {
+            log::warn!("Output device {:?} missing at startup", device.name);
+            status = Some(format!(
+                "Output device \"{}\" not found, using the default",
+                device.name
+            ));
+        }
+
+        let player_settings = settings.clone();
+        let (player, events) = PlayerHandle::spawn(move || {
+            let settings = player_settings;
+
+            let mut player = Player::new();
+            player.resample_quality = settings.resample_quality;
+            player.crossfade_secs = settings.crossfade_secs;
+            player.crossfade_curve = settings.crossfade_curve;
+            player.fade_secs = settings.fade_ms as f32 / 1000.0;
+            player.set_speed(settings.playback_speed);
+            player.volume.set_percent(settings.volume);
+            player.volume.set_muted(settings.muted);
+            player.stereo.set_balance(settings.balance);
+            player.stereo.set_mono(settings.mono);
+            player.stereo.set_swapped(settings.swap_channels);
+            player.eq.set_gains(&settings.eq_gains);
+            player.dsp.set_slots(&settings.dsp_chain);
+            player.sink = settings.sink;
+            player.set_device(settings.output_device);
+            player
+        });
+        let dsp_slots = normalize_slots(&settings.dsp_chain);
+
+        let eq_presets = persistence::load_eq_presets().unwrap_or_else(|err| {
+            log::warn!("Could not load EQ presets: {err}");
+            vec![]
+        });
+
         Self {
             screen: AppScreen::Browser,
             browser: BrowserState::new(),
+            equalizer: EqualizerState::new(settings.eq_gains, eq_presets),
+            dsp: ListSelector::new(dsp_slots),
+            devices: ListSelector::new(vec![None]),
+            spectrum: SpectrumState::new(),
+            meters: MeterState::new(),
+            loudness_scan: None,
             library: library,
-            player: Arc::new(Mutex::new(Player::new())),
+            player,
+            events,
             play_queue: Vec::new(),
             queue_index: 0,
             autoplay_enabled: true,
             current_track: None,
             playback_duration: 0,
-            playback_start: None,
-            paused_at: None,
-            paused_duration: Duration::from_secs(0),
+            position: Duration::ZERO,
+            skips_left: 0,
+            loop_a: None,
+            loop_region: None,
+            lyrics_scroll: 0,
+            settings,
+            status_at: status.as_ref().map(|_| Instant::now()),
+            status,
         }
     }
 
-    pub fn player_mut(&self) -> std::sync::MutexGuard<'_, Player> {
-        self.player.lock().unwrap()
-    }
-
     pub fn library_mut(&self) -> std::sync::MutexGuard<'_, LibraryState> {
         self.library.lock().unwrap()
     }
 
     pub fn update(&mut self) {
-        if self.autoplay_enabled
-            && self.player_mut().is_loaded()
-            && self.player_mut().is_done()
-            && !self.player_mut().is_playing
-        {
-            self.play_next_track();
+        if self.status_at.is_some_and(|at| at.elapsed() > STATUS_TIMEOUT) {
+            self.status = None;
+            self.status_at = None;
+        }
+
+        for event in self.events.poll() {
+            self.handle_player_event(event);
+        }
+
+        self.meters.update(&self.player.meter);
+        self.update_loudness_scan();
+
+        if self.screen == AppScreen::Visualizer {
+            self.spectrum.update(&self.player.tap, &self.settings.visualizer);
+        }
+    }
+
+    fn handle_player_event(&mut self, event: PlayerEvent) {
+        match event {
+            PlayerEvent::TrackStarted(path) => self.on_track_started(&path),
+            PlayerEvent::TrackFinished {
+                path,
+                playback_ended: true,
+            } => self.on_playback_ended(&path),
+            PlayerEvent::TrackFinished { .. } => {}
+            PlayerEvent::CrossfadeUnavailable(path) => {
+                let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+                self.set_status(format!("No crossfade after {name}: its length is unknown"));
+            }
+            PlayerEvent::PositionChanged(position) => self.position = position,
+            PlayerEvent::Error(err) => {
+                log::error!("{err}");
+                self.set_status(err.to_string());
+
+                // A queued next track that fails to open is just not played;
+                // only a failure of the track we asked for ends playback
+                let current = self.current_track.as_ref().map(|t| t.path.clone());
+                match err.path() {
+                    Some(path) if Some(path) == current.as_deref() => self.on_track_failed(path),
+                    Some(_) => {}
+                    None => self.current_track = None,
+                }
+            }
+            PlayerEvent::DeviceLost(reason) => {
+                log::warn!("Output device lost: {reason}");
+                self.set_status(format!("Audio device lost: {reason}"));
+                self.player.send(PlayerCommand::CloseOutput);
+            }
+        }
+    }
+
+    pub fn set_status(&mut self, message: impl Into<String>) {
+        self.status = Some(message.into());
+        self.status_at = Some(Instant::now());
+    }
+
+    /// Start `path` and make it the current track. If it cannot be played,
+    /// the player reports why and, with autoplay on, we skip ahead to the
+    /// track after it.
+    pub fn play_path(&mut self, path: &Path) {
+        // Give up after trying every track once
+        let tracks = self.library_mut().visible_tracks().len();
+        self.skips_left = tracks;
+        self.start_track(path);
+    }
+
+    fn start_track(&mut self, path: &Path) {
+        let track = {
+            let mut lib = self.library_mut();
+            lib.select_track_by_path(path);
+            lib.track_by_path(path).cloned()
+        };
+
+        let gain = match &track {
+            Some(track) => self.replaygain_factor(track),
+            None => {
+                log::warn!("Could not find LibraryTrack for path: {:?}", path);
+                1.0
+            }
+        };
+
+        self.player.send(PlayerCommand::Play {
+            path: path.to_path_buf(),
+            gain,
+        });
+
+        // Show the track straight away; an error event takes it back
+        self.playback_duration = track.as_ref().and_then(|t| t.duration).unwrap_or(0);
+        self.current_track = track;
+        self.position = Duration::ZERO;
+        self.loop_a = None;
+        self.loop_region = None;
+        self.lyrics_scroll = 0;
+
+        self.queue_next_track();
+    }
+
+    /// The track we asked the player for could not be played
+    fn on_track_failed(&mut self, path: &Path) {
+        self.current_track = None;
+
+        let next = if self.autoplay_enabled && self.skips_left > 0 {
+            self.library_mut().next_track_path(path)
+        } else {
+            None
+        };
+
+        if let Some(next) = next {
+            self.skips_left -= 1;
+            self.start_track(&next);
         }
     }
 
     pub fn goto_screen(&mut self, screen: AppScreen) {
+        if screen == AppScreen::Output {
+            self.refresh_devices();
+        }
+
         self.screen = screen
     }
 
+    /// Enumerate the output devices again, keeping the current one selected
+    pub fn refresh_devices(&mut self) {
+        let mut entries = vec![None];
+        entries.extend(list_output_devices().into_iter().map(Some));
+
+        let current = self.settings.output_device.clone();
+        let index = entries
+            .iter()
+            .position(|entry| entry.as_ref().map(|d| &d.selection) == current.as_ref())
+            .unwrap_or(0);
+
+        self.devices.set_entries(entries);
+        self.devices.selected = index;
+        self.devices.state.select(Some(index));
+    }
+
+    /// Switch output to the device selected on the output screen, carrying
+    /// on with the current track where it was
+    pub fn select_output_device(&mut self) {
+        let Some(entry) = self.devices.selected_item() else {
+            return;
+        };
+        let selection = entry.as_ref().map(|d| d.selection.clone());
+
+        if selection == self.settings.output_device {
+            return;
+        }
+
+        // Carry on with whatever the player has, where it has got to
+        let state = self.player.state();
+        let resume = state.track.map(|path| (path, state.position, state.paused));
+        let loop_region = self.loop_region;
+
+        self.player.send(PlayerCommand::SetDevice(selection.clone()));
+        self.set_status(match &selection {
+            Some(device) => format!("Output: {} ({})", device.name, device.host),
+            None => "Output: system default".to_string(),
+        });
+        self.settings.output_device = selection;
+
+        self.persist_settings();
+
+        if let Some((path, position, paused)) = resume {
+            self.start_track(&path);
+            self.player.send(PlayerCommand::Pause(paused));
+            self.seek_to(position);
+
+            if let Some((a, b)) = loop_region {
+                self.set_loop(a, b);
+            }
+        }
+    }
+
     pub fn play_next_track(&mut self) {
         if self.queue_index + 1 < self.play_queue.len() {
             self.queue_index += 1;
             let next_path = self.play_queue[self.queue_index].clone();
 
-           // Scope the library borrow once
-            let next_track = {
-                let mut lib = self.library_mut();
-                lib.select_track_by_path(&next_path);
-                lib.track_by_path(&next_path).cloned()
+            self.play_path(&next_path);
+        } else {
+            log::debug!("Reached end of queue");
+            self.queue_index = 0;
+            self.play_queue.clear();
+            self.current_track = None;
+            self.player.send(PlayerCommand::Stop);
+        }
+    }
+
+    /// Tell the player which track follows the current one, so it can be
+    /// decoded ahead and played back to back without a gap.
+    pub fn queue_next_track(&mut self) {
+        let next = match &self.current_track {
+            Some(current) if self.autoplay_enabled => {
+                let lib = self.library_mut();
+                lib.next_track_path(&current.path).map(|path| {
+                    let next = lib.track_by_path(&path);
+
+                    let same_album = next.is_some_and(|next| {
+                        next.album == current.album && next.album_artist == current.album_artist
+                    });
+                    let gain = next
+                        .map(|next| {
+                            let album_context = lib.is_album_context(&path);
+                            self.settings.replaygain.factor(&lib.replaygain_for(next), album_context)
+                        })
+                        .unwrap_or(1.0);
+
+                    (path, gain, same_album)
+                })
+            }
+            _ => None,
+        };
+
+        let allow_crossfade = match &next {
+            Some((_, _, same_album)) => !(self.settings.album_aware_crossfade && *same_album),
+            None => false,
+        };
+
+        self.player.send(PlayerCommand::Enqueue {
+            next: next.map(|(path, gain, _)| (path, gain)),
+            allow_crossfade,
+        });
+    }
+
+    /// Measure the loudness of every album not measured yet, in the
+    /// background
+    pub fn start_loudness_scan(&mut self) {
+        if self.loudness_scan.is_some() {
+            self.set_status("Loudness scan already running");
+            return;
+        }
+
+        self.loudness_scan = Some(LoudnessScan::spawn(
+            Arc::clone(&self.library),
+            self.settings.write_replaygain_tags,
+        ));
+    }
+
+    /// Show how far the loudness scan has got, and the outcome once done
+    fn update_loudness_scan(&mut self) {
+        let Some(scan) = &mut self.loudness_scan else {
+            return;
+        };
+
+        if scan.is_finished() {
+            let (done, _) = scan.progress();
+            let failed = scan.failed();
+            self.loudness_scan = None;
+
+            let message = if failed > 0 {
+                format!("Loudness scan done: {done} tracks, {failed} could not be read")
+            } else {
+                format!("Loudness scan done: {done} tracks")
             };
+            self.set_status(message);
+
+            // Gains of the queued track may have changed
+            self.queue_next_track();
+        } else if let Some((done, total)) = scan.progress_update() {
+            self.set_status(format!("Measuring loudness: {done}/{total} tracks"));
+        }
+    }
+
+    /// Linear ReplayGain factor for `track` under the current settings
+    pub fn replaygain_factor(&self, track: &LibraryTrack) -> f32 {
+        let lib = self.library_mut();
+        let album_context = lib.is_album_context(&track.path);
+        self.settings.replaygain.factor(&lib.replaygain_for(track), album_context)
+    }
+
+    /// Write the settings to `settings.json`, logging any failure
+    fn persist_settings(&self) {
+        if let Err(err) = persistence::save_settings(&self.settings) {
+            log::error!("Could not save settings: {err}");
+        }
+    }
+
+    /// Nudge the volume by `delta` percent
+    pub fn change_volume(&mut self, delta: i16) {
+        let percent = (self.settings.volume as i16 + delta).clamp(0, 100) as u8;
+
+        self.settings.volume = percent;
+        self.player.send(PlayerCommand::SetVolume(percent));
+
+        self.persist_settings();
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.settings.muted = !self.settings.muted;
+        self.player.send(PlayerCommand::SetMuted(self.settings.muted));
+
+        self.persist_settings();
+    }
+
+    /// Shift the balance by `delta`, -1.0 being fully left, in steps of 0.1
+    pub fn change_balance(&mut self, delta: f32) {
+        let balance = ((self.settings.balance + delta) * 10.0).round() / 10.0;
+        let balance = balance.clamp(-1.0, 1.0);
+
+        self.settings.balance = balance;
+        self.player.send(PlayerCommand::SetBalance(balance));
 
-            if let Some(track) = next_track {
+        self.persist_settings();
+    }
+
+    pub fn toggle_mono(&mut self) {
+        self.settings.mono = !self.settings.mono;
+        self.player.send(PlayerCommand::SetMono(self.settings.mono));
+
+        self.persist_settings();
+    }
+
+    pub fn toggle_swap_channels(&mut self) {
+        self.settings.swap_channels = !self.settings.swap_channels;
+        self.player
+            .send(PlayerCommand::SetSwapChannels(self.settings.swap_channels));
+
+        self.persist_settings();
+    }
+
+    /// Push the equalizer screen's bands to the player and remember them
+    pub fn apply_eq(&mut self) {
+        let gains = self.equalizer.gains;
+
+        self.player.send(PlayerCommand::SetEqGains(gains));
+        self.settings.eq_gains = gains;
+
+        self.persist_settings();
+    }
+
+    /// Switch the selected DSP stage on or off
+    pub fn toggle_dsp_stage(&mut self) {
+        let mut slots = self.dsp.entries.clone();
+        let Some(slot) = slots.get_mut(self.dsp.selected) else {
+            return;
+        };
+
+        slot.enabled = !slot.enabled;
+        self.set_dsp_chain(slots);
+    }
+
+    /// Move the selected DSP stage earlier (`-1`) or later (`1`) in the chain
+    pub fn move_dsp_stage(&mut self, offset: isize) {
+        let index = self.dsp.selected;
+        let Some(target) = index.checked_add_signed(offset) else {
+            return;
+        };
+        if target >= self.dsp.entries.len() {
+            return;
+        }
+
+        let mut slots = self.dsp.entries.clone();
+        slots.swap(index, target);
+        self.set_dsp_chain(slots);
+
+        // Keep the cursor on the stage that moved
+        self.dsp.selected = target;
+        self.dsp.state.select(Some(target));
+    }
+
+    fn set_dsp_chain(&mut self, slots: Vec<StageSlot>) {
+        self.player.send(PlayerCommand::SetDspChain(slots.clone()));
+
+        self.dsp.entries = slots.clone();
+        self.settings.dsp_chain = slots;
+
+        self.persist_settings();
+    }
+
+    pub fn save_eq_preset(&mut self) {
+        self.equalizer.save_user_preset();
+        self.persist_eq_presets();
+    }
+
+    pub fn delete_eq_preset(&mut self) {
+        if self.equalizer.delete_selected_preset() {
+            self.persist_eq_presets();
+        }
+    }
+
+    fn persist_eq_presets(&self) {
+        if let Err(err) = persistence::save_eq_presets(&self.equalizer.user_presets()) {
+            log::error!("Could not save EQ presets: {err}");
+        }
+    }
+
+    /// Cycle off / track / album / auto. Takes effect from the next track.
+    pub fn cycle_replaygain_mode(&mut self) {
+        self.settings.replaygain.mode = self.settings.replaygain.mode.next();
+
+        self.persist_settings();
+
+        self.queue_next_track();
+    }
+
+    /// Step the crossfade length through 0, 2, 4 .. 12 seconds
+    pub fn cycle_crossfade(&mut self) {
+        let secs = self.settings.crossfade_secs.round() + 2.0;
+        let secs = if secs > MAX_CROSSFADE_SECS { 0.0 } else { secs };
+
+        self.settings.crossfade_secs = secs;
+        self.player.send(PlayerCommand::SetCrossfade {
+            secs,
+            curve: self.settings.crossfade_curve,
+        });
+
+        self.persist_settings();
+
+        self.queue_next_track();
+    }
+
+    /// Switch between the linear and equal-power crossfade curves
+    pub fn cycle_crossfade_curve(&mut self) {
+        let curve = self.settings.crossfade_curve.next();
+
+        self.settings.crossfade_curve = curve;
+        self.player.send(PlayerCommand::SetCrossfade {
+            secs: self.settings.crossfade_secs,
+            curve,
+        });
+
+        self.set_status(match curve {
+            FadeCurve::Linear => "Crossfade curve: linear",
+            FadeCurve::EqualPower => "Crossfade curve: equal power",
+        });
+        self.persist_settings();
+
+        self.queue_next_track();
+    }
+
+    /// Whether tracks of the same album play back to back without crossfade
+    pub fn toggle_album_aware_crossfade(&mut self) {
+        self.settings.album_aware_crossfade = !self.settings.album_aware_crossfade;
+
+        self.set_status(if self.settings.album_aware_crossfade {
+            "No crossfade within an album"
+        } else {
+            "Crossfade within albums too"
+        });
+        self.persist_settings();
+
+        self.queue_next_track();
+    }
+
+    /// Nudge the playback speed by `delta`, in steps of 0.1x
+    pub fn change_speed(&mut self, delta: f32) {
+        let speed = ((self.settings.playback_speed + delta) * 10.0).round() / 10.0;
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+
+        self.settings.playback_speed = speed;
+        self.player.send(PlayerCommand::SetSpeed(speed));
+
+        self.persist_settings();
+    }
+
+    /// A track became audible. Only does anything when the player moved on
+    /// to the queued track on its own; `play_path` already set up the rest.
+    fn on_track_started(&mut self, path: &Path) {
+        if self.current_track.as_ref().is_some_and(|t| t.path == path) {
+            return;
+        }
+
+        let track = {
+            let mut lib = self.library_mut();
+            lib.select_track_by_path(path);
+            lib.track_by_path(path).cloned()
+        };
+
+        match track {
+            Some(track) => {
+                log::debug!("Gapless switch to: {} – {}", track.album_artist, track.title);
+                self.playback_duration = track.duration.unwrap_or(0);
                 self.current_track = Some(track);
-                self.playback_start = Some(Instant::now());
-            } else {
-                log::warn!("Could not find LibraryTrack for path: {:?}", next_path);
-                self.current_track = None;
-                self.playback_start = None;
             }
+            None => log::warn!("Could not find LibraryTrack for path: {:?}", path),
+        }
+
+        self.position = Duration::ZERO;
+        self.loop_a = None;
+        self.loop_region = None;
+        self.lyrics_scroll = 0;
+        self.queue_next_track();
+    }
 
-            self.player_mut().play(&next_path);
+    /// The last queued track played out; with autoplay on, carry on with
+    /// whatever comes after it in the library
+    fn on_playback_ended(&mut self, path: &Path) {
+        let next_path = if self.autoplay_enabled {
+            self.library_mut().next_track_path(path)
         } else {
-            log::debug!("Reached end of queue");
-            self.queue_index = 0;
-            self.play_queue.clear();
-            self.current_track = None;
-            self.playback_start = None;
+            None
+        };
+
+        match next_path {
+            Some(next_path) => {
+                self.play_path(&next_path);
+
+                if let Some(next_track) = &self.current_track {
+                    log::debug!(
+                        "Autoplay switched to: {} – {}",
+                        next_track.album_artist,
+                        next_track.title
+                    );
+                }
+            }
+            None => self.queue_next_track(),
         }
     }
 
+    pub fn toggle_autoplay(&mut self) {
+        self.autoplay_enabled = !self.autoplay_enabled;
+        self.queue_next_track();
+    }
 
     pub fn set_play_queue(&mut self, tracks: Vec<PathBuf>, start_index: usize) {
         self.play_queue = tracks;
         self.queue_index = start_index;
     }
 
-    pub fn pause(&mut self) {
-        let mut player = self.player.lock().unwrap();
-        player.set_paused(true);
-        self.paused_at = Some(Instant::now());
+    /// Time elapsed in the current track, as played by the device
+    pub fn elapsed(&self) -> Duration {
+        if self.current_track.is_some() {
+            self.position
+        } else {
+            Duration::ZERO
+        }
     }
 
-    pub fn resume(&mut self) {
-        let mut player = self.player.lock().unwrap();
-        player.set_paused(false);
+    pub fn seek_to(&mut self, position: Duration) {
+        let Some(track) = &self.current_track else {
+            return;
+        };
 
-        if let Some(paused_at) = self.paused_at {
-            self.paused_duration += paused_at.elapsed();
-        }
+        let position = match track.duration {
+            Some(dur) => position.min(Duration::from_secs(dur)),
+            None => position,
+        };
 
-        self.paused_at = None;
+        self.player.send(PlayerCommand::Seek(position.as_secs_f64()));
+        self.position = position;
     }
 
-    pub fn toggle_pause(&mut self) {
-        let is_paused = {
-            let player = self.player.lock().unwrap();
-            player.is_paused
+    pub fn seek_by(&mut self, delta_secs: i64) {
+        let elapsed = self.elapsed();
+        let delta = Duration::from_secs(delta_secs.unsigned_abs());
+
+        let target = if delta_secs < 0 {
+            elapsed.saturating_sub(delta)
+        } else {
+            elapsed + delta
         };
 
-        if is_paused {
-            self.resume();
+        self.seek_to(target);
+    }
+
+    /// Step through the A-B loop: mark A at the current position, then B,
+    /// which starts looping, then turn the loop off again
+    pub fn cycle_loop(&mut self) {
+        if self.current_track.is_none() {
+            return;
+        }
+
+        let position = self.elapsed();
+
+        if self.loop_region.is_some() {
+            self.loop_a = None;
+            self.loop_region = None;
+            self.player.send(PlayerCommand::SetLoop(None));
+            self.set_status("Loop off");
+        } else if let Some(a) = self.loop_a.take() {
+            if position > a {
+                self.set_loop(a, position);
+                self.set_status(format!(
+                    "Looping {} – {}",
+                    format_time(a),
+                    format_time(position)
+                ));
+            } else {
+                self.set_status("Loop end has to come after its start");
+            }
         } else {
-            self.pause();
+            self.loop_a = Some(position);
+            self.set_status(format!(
+                "Loop start {}, press l again at the end",
+                format_time(position)
+            ));
         }
     }
+
+    fn set_loop(&mut self, a: Duration, b: Duration) {
+        self.loop_region = Some((a, b));
+        self.player
+            .send(PlayerCommand::SetLoop(Some((a.as_secs_f64(), b.as_secs_f64()))));
+    }
+
+    /// Jump to a fraction (0.0..=1.0) of the current track's duration
+    pub fn seek_fraction(&mut self, fraction: f64) {
+        let Some(track) = &self.current_track else {
+            return;
+        };
+
+        // Tracks added before lengths were read for MP3s pick them up when
+        // their directory is added again
+        let Some(dur) = track.duration else {
+            self.set_status("Track length unknown, add the directory again to read it");
+            return;
+        };
+
+        let target = dur as f64 * fraction.clamp(0.0, 1.0);
+        self.seek_to(Duration::from_secs_f64(target));
+    }
+
+    pub fn change_visualizer_bars(&mut self, more: bool) {
+        self.settings.visualizer.change_bars(more);
+        self.persist_settings();
+    }
+
+    /// Switch the spectrum between log and linear frequency spacing
+    pub fn toggle_visualizer_scale(&mut self) {
+        self.settings.visualizer.log_scale = !self.settings.visualizer.log_scale;
+        self.persist_settings();
+    }
+
+    pub fn toggle_peak_hold(&mut self) {
+        self.settings.visualizer.peak_hold = !self.settings.visualizer.peak_hold;
+        self.persist_settings();
+    }
+
+    /// Scroll unsynced lyrics by one line
+    pub fn scroll_lyrics(&mut self, down: bool) {
+        let Some(lyrics) = self.current_track.as_ref().and_then(|t| t.lyrics.as_ref()) else {
+            return;
+        };
+        if lyrics.is_synced() {
+            return;
+        }
+
+        let last = lyrics.lines().len().saturating_sub(1);
+        self.lyrics_scroll = if down {
+            (self.lyrics_scroll + 1).min(last)
+        } else {
+            self.lyrics_scroll.saturating_sub(1)
+        };
+    }
+
+    /// Cycle the resampler quality. Takes effect from the next track.
+    pub fn cycle_resample_quality(&mut self) {
+        let quality = self.settings.resample_quality.next();
+
+        self.settings.resample_quality = quality;
+        self.player.send(PlayerCommand::SetResampleQuality(quality));
+
+        self.persist_settings();
+    }
+
+    /// Left to the player, which knows whether it is paused right now
+    pub fn toggle_pause(&mut self) {
+        self.player.send(PlayerCommand::TogglePause);
+    }
+}
+
+/// mm:ss
+fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("{:02}:{:02}", secs / 60, secs % 60)
 }
@@ -0,0 +1,118 @@
+use crate::list::ListSelector;
+use crate::player::{EqPreset, MAX_BAND_DB, builtin_presets};
+
+/// Change applied to a band per key press, in dB
+const BAND_STEP_DB: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EqualizerFocus {
+    Bands,
+    Presets,
+}
+
+pub struct EqualizerState {
+    pub gains: [f32; 10],
+    pub band: usize,
+    pub focus: EqualizerFocus,
+
+    /// Built-in presets followed by the user's own
+    pub presets: ListSelector<EqPreset>,
+}
+
+impl EqualizerState {
+    pub fn new(gains: [f32; 10], user_presets: Vec<EqPreset>) -> Self {
+        let mut presets = builtin_presets();
+        presets.extend(user_presets);
+
+        Self {
+            gains,
+            band: 0,
+            focus: EqualizerFocus::Bands,
+            presets: ListSelector::new(presets),
+        }
+    }
+
+    pub fn tab_focus(&mut self) {
+        self.focus = match self.focus {
+            EqualizerFocus::Bands => EqualizerFocus::Presets,
+            EqualizerFocus::Presets => EqualizerFocus::Bands,
+        };
+    }
+
+    pub fn move_left(&mut self) {
+        self.band = self.band.saturating_sub(1);
+    }
+
+    pub fn move_right(&mut self) {
+        self.band = (self.band + 1).min(self.gains.len() - 1);
+    }
+
+    pub fn move_up(&mut self) {
+        match self.focus {
+            EqualizerFocus::Bands => self.adjust_band(BAND_STEP_DB),
+            EqualizerFocus::Presets => self.presets.move_up(),
+        }
+    }
+
+    pub fn move_down(&mut self) {
+        match self.focus {
+            EqualizerFocus::Bands => self.adjust_band(-BAND_STEP_DB),
+            EqualizerFocus::Presets => self.presets.move_down(),
+        }
+    }
+
+    fn adjust_band(&mut self, delta: f32) {
+        let gain = &mut self.gains[self.band];
+        *gain = (*gain + delta).clamp(-MAX_BAND_DB, MAX_BAND_DB);
+    }
+
+    /// Copy the selected preset's gains into the bands
+    pub fn apply_selected_preset(&mut self) {
+        if let Some(preset) = self.presets.selected_item() {
+            self.gains = preset.gains;
+        }
+    }
+
+    fn builtin_count() -> usize {
+        builtin_presets().len()
+    }
+
+    pub fn is_user_preset(&self, index: usize) -> bool {
+        index >= Self::builtin_count()
+    }
+
+    pub fn user_presets(&self) -> Vec<EqPreset> {
+        self.presets.entries[Self::builtin_count()..].to_vec()
+    }
+
+    /// Store the current bands as a new user preset and select it
+    pub fn save_user_preset(&mut self) {
+        let number = (1..)
+            .find(|n| {
+                let name = format!("User {n}");
+                !self.presets.entries.iter().any(|p| p.name == name)
+            })
+            .unwrap_or(1);
+
+        self.presets.entries.push(EqPreset {
+            name: format!("User {number}"),
+            gains: self.gains,
+        });
+
+        self.presets.selected = self.presets.entries.len() - 1;
+        self.presets.state.select(Some(self.presets.selected));
+    }
+
+    /// Remove the selected preset if it is a user preset
+    pub fn delete_selected_preset(&mut self) -> bool {
+        let index = self.presets.selected;
+        if !self.is_user_preset(index) || index >= self.presets.entries.len() {
+            return false;
+        }
+
+        self.presets.entries.remove(index);
+        self.presets.selected = index.min(self.presets.entries.len() - 1);
+        self.presets.state.select(Some(self.presets.selected));
+        true
+    }
+}
@@ -0,0 +1,334 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const MARKER: &[u8; 4] = b"fLaC";
+
+/// Metadata block types, and the flag on the header of the last block
+const PADDING: u8 = 1;
+const VORBIS_COMMENT: u8 = 4;
+const LAST_BLOCK: u8 = 0x80;
+
+/// Largest metadata block, its length being 24 bits
+const MAX_BLOCK_LEN: usize = 0xff_ffff;
+
+/// Padding left after the metadata when the file has to be rewritten, so
+/// the next change fits in place
+const NEW_PADDING: usize = 4096;
+
+/// Vendor string of a Vorbis comment block created from scratch
+const VENDOR: &str = "shelltrax";
+
+struct Block {
+    kind: u8,
+    data: Vec<u8>,
+}
+
+/// Set Vorbis comments in a FLAC file, replacing any with the same keys
+/// (compared without case) and keeping all others.
+///
+/// The metadata is written over the old one when it fits in the space the
+/// old one and its padding took; otherwise the whole file is rewritten
+/// next to the original and moved over it.
+pub fn set_comments(path: &Path, comments: &[(&str, String)]) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let (mut blocks, audio_start) = read_blocks(&mut reader)?;
+
+    let old_len = audio_start as usize - MARKER.len();
+
+    let position = blocks.iter().position(|b| b.kind == VORBIS_COMMENT);
+    let mut vorbis = match position {
+        Some(i) => VorbisComment::parse(&blocks[i].data)?,
+        None => VorbisComment::new(),
+    };
+    vorbis.set(comments);
+
+    let data = vorbis.encode();
+    anyhow::ensure!(data.len() <= MAX_BLOCK_LEN, "Vorbis comments too long");
+    match position {
+        Some(i) => blocks[i].data = data,
+        // Right after STREAMINFO, which always comes first
+        None => blocks.insert(
+            1,
+            Block {
+                kind: VORBIS_COMMENT,
+                data,
+            },
+        ),
+    }
+
+    blocks.retain(|b| b.kind != PADDING);
+    let new_len: usize = blocks.iter().map(|b| 4 + b.data.len()).sum();
+
+    // Same size as before, with padding taking up the difference
+    if new_len == old_len || (new_len + 4 <= old_len && old_len - new_len - 4 <= MAX_BLOCK_LEN) {
+        if new_len < old_len {
+            let padding = vec![0; old_len - new_len - 4];
+            blocks.push(Block {
+                kind: PADDING,
+                data: padding,
+            });
+        }
+        drop(reader);
+
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        write_metadata(&mut file, &blocks)?;
+        return Ok(file.flush()?);
+    }
+
+    blocks.push(Block {
+        kind: PADDING,
+        data: vec![0; NEW_PADDING],
+    });
+
+    let temp = path.with_extension("flac.tmp");
+    let result = (|| -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        write_metadata(&mut writer, &blocks)?;
+
+        reader.seek(SeekFrom::Start(audio_start))?;
+        io::copy(&mut reader, &mut writer)?;
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => Ok(fs::rename(&temp, path)?),
+        Err(err) => {
+            let _ = fs::remove_file(&temp);
+            Err(err)
+        }
+    }
+}
+
+/// Every metadata block, and where the audio frames start
+fn read_blocks(reader: &mut impl Read) -> anyhow::Result<(Vec<Block>, u64)> {
+    let mut marker = [0; 4];
+    reader.read_exact(&mut marker)?;
+    anyhow::ensure!(&marker == MARKER, "Not a FLAC stream");
+
+    let mut blocks = Vec::new();
+    let mut offset = MARKER.len() as u64;
+
+    loop {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header)?;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+        let mut data = vec![0; len];
+        reader.read_exact(&mut data)?;
+        blocks.push(Block {
+            kind: header[0] & !LAST_BLOCK,
+            data,
+        });
+        offset += 4 + len as u64;
+
+        if header[0] & LAST_BLOCK != 0 {
+            break;
+        }
+    }
+
+    Ok((blocks, offset))
+}
+
+fn write_metadata(writer: &mut impl Write, blocks: &[Block]) -> io::Result<()> {
+    writer.write_all(MARKER)?;
+
+    for (i, block) in blocks.iter().enumerate() {
+        let last = if i + 1 == blocks.len() { LAST_BLOCK } else { 0 };
+        let len = (block.data.len() as u32).to_be_bytes();
+
+        writer.write_all(&[block.kind | last, len[1], len[2], len[3]])?;
+        writer.write_all(&block.data)?;
+    }
+
+    Ok(())
+}
+
+/// The `KEY=value` comments of a Vorbis comment block, kept as raw bytes so
+/// entries that are not valid UTF-8 survive untouched
+struct VorbisComment {
+    vendor: Vec<u8>,
+    comments: Vec<Vec<u8>>,
+}
+
+impl VorbisComment {
+    fn new() -> Self {
+        Self {
+            vendor: VENDOR.as_bytes().to_vec(),
+            comments: Vec::new(),
+        }
+    }
+
+    fn parse(mut data: &[u8]) -> anyhow::Result<Self> {
+        let vendor = take_string(&mut data)?;
+        let count = take_u32(&mut data)?;
+
+        let comments = (0..count)
+            .map(|_| take_string(&mut data))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { vendor, comments })
+    }
+
+    fn set(&mut self, comments: &[(&str, String)]) {
+        self.comments.retain(|comment| {
+            let key = comment.split(|b| *b == b'=').next().unwrap_or_default();
+            !comments
+                .iter()
+                .any(|(new, _)| new.as_bytes().eq_ignore_ascii_case(key))
+        });
+
+        for (key, value) in comments {
+            self.comments.push(format!("{key}={value}").into_bytes());
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        data.extend_from_slice(&(self.vendor.len() as u32).to_le_bytes());
+        data.extend_from_slice(&self.vendor);
+        data.extend_from_slice(&(self.comments.len() as u32).to_le_bytes());
+        for comment in &self.comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment);
+        }
+
+        data
+    }
+}
+
+fn take_u32(data: &mut &[u8]) -> anyhow::Result<u32> {
+    anyhow::ensure!(data.len() >= 4, "Vorbis comment block cut short");
+    let (bytes, rest) = data.split_at(4);
+    *data = rest;
+
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn take_string(data: &mut &[u8]) -> anyhow::Result<Vec<u8>> {
+    let len = take_u32(data)? as usize;
+    anyhow::ensure!(data.len() >= len, "Vorbis comment block cut short");
+    let (bytes, rest) = data.split_at(len);
+    *data = rest;
+
+    Ok(bytes.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUDIO: &[u8] = b"\xff\xf8 not really frames";
+
+    /// A FLAC file with a blank STREAMINFO, the given comments and padding,
+    /// then `AUDIO`
+    fn write_flac(name: &str, comments: &[&str], padding: usize) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("shelltrax-flac-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+
+        let mut blocks = vec![Block {
+            kind: 0,
+            data: vec![0; 34],
+        }];
+        if !comments.is_empty() {
+            let vorbis = VorbisComment {
+                vendor: b"reference libFLAC".to_vec(),
+                comments: comments.iter().map(|c| c.as_bytes().to_vec()).collect(),
+            };
+            blocks.push(Block {
+                kind: VORBIS_COMMENT,
+                data: vorbis.encode(),
+            });
+        }
+        if padding > 0 {
+            blocks.push(Block {
+                kind: PADDING,
+                data: vec![0; padding],
+            });
+        }
+
+        let mut file = File::create(&path).unwrap();
+        write_metadata(&mut file, &blocks).unwrap();
+        file.write_all(AUDIO).unwrap();
+        path
+    }
+
+    /// The comments of the file, and the bytes after its metadata
+    fn read_back(path: &Path) -> (Vec<String>, Vec<u8>) {
+        let bytes = fs::read(path).unwrap();
+        let (blocks, audio_start) = read_blocks(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(blocks[0].kind, 0, "STREAMINFO has to stay first");
+        let vorbis = blocks.iter().find(|b| b.kind == VORBIS_COMMENT).unwrap();
+        let comments = VorbisComment::parse(&vorbis.data)
+            .unwrap()
+            .comments
+            .into_iter()
+            .map(|c| String::from_utf8(c).unwrap())
+            .collect();
+
+        (comments, bytes[audio_start as usize..].to_vec())
+    }
+
+    fn gain(value: &str) -> Vec<(&'static str, String)> {
+        vec![("REPLAYGAIN_TRACK_GAIN", value.to_string())]
+    }
+
+    #[test]
+    fn replaces_matching_keys_and_keeps_the_rest() {
+        let path = write_flac(
+            "replace.flac",
+            &["TITLE=Song", "replaygain_track_gain=1.00 dB"],
+            64,
+        );
+
+        set_comments(&path, &gain("-3.00 dB")).unwrap();
+
+        let (comments, audio) = read_back(&path);
+        assert_eq!(
+            comments,
+            vec!["TITLE=Song", "REPLAYGAIN_TRACK_GAIN=-3.00 dB"]
+        );
+        assert_eq!(audio, AUDIO);
+    }
+
+    #[test]
+    fn fits_into_padding_in_place() {
+        let path = write_flac("in-place.flac", &["TITLE=Song"], 256);
+        let len = fs::metadata(&path).unwrap().len();
+
+        set_comments(&path, &gain("-3.00 dB")).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        assert_eq!(read_back(&path).1, AUDIO);
+    }
+
+    #[test]
+    fn rewrites_when_there_is_no_room() {
+        let path = write_flac("rewrite.flac", &[], 0);
+
+        set_comments(&path, &gain("-3.00 dB")).unwrap();
+
+        let (comments, audio) = read_back(&path);
+        assert_eq!(comments, vec!["REPLAYGAIN_TRACK_GAIN=-3.00 dB"]);
+        assert_eq!(audio, AUDIO);
+        assert!(!path.with_extension("flac.tmp").exists());
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let dir = std::env::temp_dir().join(format!("shelltrax-flac-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("not.flac");
+        fs::write(&path, b"RIFF....WAVE").unwrap();
+
+        assert!(set_comments(&path, &gain("-3.00 dB")).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"RIFF....WAVE");
+    }
+}
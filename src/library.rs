@@ -6,16 +6,19 @@ use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
 
-use id3::Tag as Id3Tag;
+use id3::frame::{Content, ExtendedText, TimestampFormat};
+use id3::{Frame, Tag as Id3Tag};
 use symphonia::core::{
-    formats::FormatOptions,
+    formats::{FormatOptions, FormatReader},
     io::MediaSourceStream,
-    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    meta::{MetadataOptions, StandardTagKey},
     probe::Hint,
-    probe::ProbeResult,
 };
 use symphonia::default::get_probe;
 
+use crate::flac;
+use crate::loudness::Loudness;
+use crate::lyrics::{self, Lyrics};
 use crate::persistence;
 
 #[derive(Debug, Clone)]
@@ -74,9 +77,11 @@ impl LibraryState {
             if let Some(artist) = self.artists.iter_mut().find(|a| a.name == track.album_artist) {
                 // Check if album exists
                 if let Some(album) = artist.albums.iter_mut().find(|a| a.name == track.album) {
-                    // Check for duplicate by path
-                    let already_exists = album.tracks.iter().any(|t| t.path == track.path);
-                    if !already_exists {
+                    // Check for duplicate by path; adding it again refreshes
+                    // what was read from its tags
+                    if let Some(existing) = album.tracks.iter_mut().find(|t| t.path == track.path) {
+                        existing.update_from_scan(track);
+                    } else {
                         album.tracks.push(track);
                         album.tracks.sort_by_key(|t| t.track_number.unwrap_or(999));
                     }
@@ -84,6 +89,7 @@ impl LibraryState {
                     artist.albums.push(AlbumNode {
                         name: track.album.clone(),
                         tracks: vec![track],
+                        loudness: None,
                     });
                 }
             } else {
@@ -92,6 +98,7 @@ impl LibraryState {
                     albums: vec![AlbumNode {
                         name: track.album.clone(),
                         tracks: vec![track],
+                        loudness: None,
                     }],
                     expanded: false,
                 });
@@ -316,6 +323,22 @@ impl LibraryState {
         None
     }
 
+    /// Whether `path` is being played as part of an album: a neighbouring
+    /// track in the current list comes from the same album
+    pub fn is_album_context(&self, path: &Path) -> bool {
+        let tracks = self.visible_tracks();
+        let Some(i) = tracks.iter().position(|t| t.path == path) else {
+            return false;
+        };
+
+        let current = &tracks[i];
+        let same_album = |other: Option<&LibraryTrack>| {
+            other.is_some_and(|o| o.album == current.album && o.album_artist == current.album_artist)
+        };
+
+        same_album(i.checked_sub(1).and_then(|prev| tracks.get(prev))) || same_album(tracks.get(i + 1))
+    }
+
     pub fn select_track_by_path(&mut self, path: &Path) {
         let tracks = self.visible_tracks();
         if let Some(i) = tracks.iter().position(|t| &t.path == path) {
@@ -324,8 +347,84 @@ impl LibraryState {
         }
     }
 
+    fn album_of(&self, path: &Path) -> Option<&AlbumNode> {
+        self.artists
+            .iter()
+            .flat_map(|a| a.albums.iter())
+            .find(|alb| alb.tracks.iter().any(|t| t.path == path))
+    }
+
+    /// ReplayGain values for playback: the track's tags, with whatever they
+    /// lack filled in from the loudness scan
+    pub fn replaygain_for(&self, track: &LibraryTrack) -> ReplayGain {
+        let mut values = track.replaygain;
+
+        if let Some(loudness) = track.loudness {
+            values.track_gain.get_or_insert(loudness.replaygain_db());
+            values.track_peak.get_or_insert(loudness.true_peak);
+        }
+
+        if let Some(loudness) = self.album_of(&track.path).and_then(|alb| alb.loudness) {
+            values.album_gain.get_or_insert(loudness.replaygain_db());
+            values.album_peak.get_or_insert(loudness.true_peak);
+        }
+
+        values
+    }
+
+    /// Record loudness scan results for an album and its tracks
+    pub fn store_loudness(
+        &mut self,
+        artist: &str,
+        album: &str,
+        tracks: &[(PathBuf, Option<Loudness>)],
+        album_loudness: Option<Loudness>,
+    ) {
+        let Some(node) = self
+            .artists
+            .iter_mut()
+            .filter(|a| a.name == artist)
+            .flat_map(|a| a.albums.iter_mut())
+            .find(|alb| alb.name == album)
+        else {
+            return;
+        };
+
+        for (path, loudness) in tracks {
+            if let Some(track) = node.tracks.iter_mut().find(|t| &t.path == path) {
+                track.loudness = *loudness;
+                track.loudness_scanned = true;
+            }
+        }
+        node.loudness = album_loudness;
+    }
+
+    /// The file at `path` now carries these ReplayGain tags
+    pub fn set_replaygain_tags(&mut self, path: &Path, values: ReplayGain) {
+        let library_tracks = self
+            .artists
+            .iter_mut()
+            .flat_map(|a| a.albums.iter_mut())
+            .flat_map(|alb| alb.tracks.iter_mut());
+
+        for track in self.tracks.iter_mut().chain(library_tracks) {
+            if track.path == path {
+                track.replaygain = values;
+            }
+        }
+    }
+
     pub fn track_by_path(&self, path: &Path) -> Option<&LibraryTrack> {
-        self.tracks.iter().find(|t| t.path == path)
+        let library_tracks = self
+            .artists
+            .iter()
+            .flat_map(|a| a.albums.iter())
+            .flat_map(|alb| alb.tracks.iter());
+
+        self.tracks
+            .iter()
+            .chain(library_tracks)
+            .find(|t| t.path == path)
     }
 }
 
@@ -338,12 +437,85 @@ pub struct LibraryTrack {
     pub track_number: Option<u32>,
     pub album_artist: String,
     pub duration: Option<u64>,
+    #[serde(default)]
+    pub replaygain: ReplayGain,
+
+    /// Measured by the loudness scan, `None` until then, or for tracks the
+    /// scan found silent or could not decode
+    #[serde(default)]
+    pub loudness: Option<Loudness>,
+
+    /// Set once the loudness scan has been through the track, whether or
+    /// not it found a loudness
+    #[serde(default)]
+    pub loudness_scanned: bool,
+
+    /// From a sibling `.lrc` file or the tags, read when the track is added
+    #[serde(default)]
+    pub lyrics: Option<Lyrics>,
+}
+
+impl LibraryTrack {
+    /// Neither measured nor found unmeasurable yet. Libraries saved before
+    /// `loudness_scanned` existed only have the loudness to go by.
+    pub fn needs_loudness_scan(&self) -> bool {
+        !self.loudness_scanned && self.loudness.is_none()
+    }
+
+    /// Take over what a new scan of the same file read, so a library saved
+    /// by an older version catches up when its directory is added again.
+    /// Measured loudness is kept.
+    fn update_from_scan(&mut self, scanned: LibraryTrack) {
+        self.duration = scanned.duration;
+        self.replaygain = scanned.replaygain;
+        self.lyrics = scanned.lyrics;
+    }
+}
+
+/// ReplayGain values read from a track's tags. Gains are in dB, peaks are
+/// linear sample amplitudes where 1.0 is full scale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    /// Parses values like `-6.54 dB` or `0.988312`
+    fn parse_value(value: &str) -> Option<f32> {
+        let value = value.trim();
+        let value = value
+            .strip_suffix("dB")
+            .or_else(|| value.strip_suffix("db"))
+            .unwrap_or(value);
+
+        value.trim().parse().ok()
+    }
+
+    /// Record a tag if `key` is one of the ReplayGain keys
+    fn set_from_tag(&mut self, key: &str, value: &str) {
+        let slot = match key.to_ascii_uppercase().as_str() {
+            "REPLAYGAIN_TRACK_GAIN" => &mut self.track_gain,
+            "REPLAYGAIN_TRACK_PEAK" => &mut self.track_peak,
+            "REPLAYGAIN_ALBUM_GAIN" => &mut self.album_gain,
+            "REPLAYGAIN_ALBUM_PEAK" => &mut self.album_peak,
+            _ => return,
+        };
+
+        *slot = Self::parse_value(value);
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AlbumNode {
     pub name: String,
     pub tracks: Vec<LibraryTrack>,
+
+    /// Measured over all tracks by the loudness scan
+    #[serde(default)]
+    pub loudness: Option<Loudness>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -353,6 +525,33 @@ pub struct ArtistNode {
     pub expanded: bool,
 }
 
+/// Tags read from an audio file while scanning
+struct TrackTags {
+    title: String,
+    artist: String,
+    album: String,
+    track_number: Option<u32>,
+    album_artist: String,
+    duration: Option<u64>,
+    replaygain: ReplayGain,
+    lyrics: Option<Lyrics>,
+}
+
+impl Default for TrackTags {
+    fn default() -> Self {
+        Self {
+            title: "Unknown Title".into(),
+            artist: "Unknown Artist".into(),
+            album: "Unknown Album".into(),
+            track_number: None,
+            album_artist: "Unknown Album Artist".into(),
+            duration: None,
+            replaygain: ReplayGain::default(),
+            lyrics: None,
+        }
+    }
+}
+
 /// Scans a path recursively and parses audio files into LibraryTrack entries.
 pub fn scan_path_for_tracks(path: &Path) -> Vec<LibraryTrack> {
     let mut tracks = Vec::new();
@@ -369,7 +568,7 @@ pub fn scan_path_for_tracks(path: &Path) -> Vec<LibraryTrack> {
             .and_then(|ext| ext.to_str())
             .map(|s| s.to_ascii_lowercase());
 
-        let (title, artist, album, track_number, album_artist, duration) = match ext.as_deref() {
+        let tags = match ext.as_deref() {
             Some("mp3") => extract_id3_tags(path),
             Some("flac") => extract_symphonia_tags(path),
             _ => continue,
@@ -377,19 +576,23 @@ pub fn scan_path_for_tracks(path: &Path) -> Vec<LibraryTrack> {
 
         tracks.push(LibraryTrack {
             path: path.to_path_buf(),
-            title,
-            artist,
-            album,
-            track_number,
-            album_artist,
-            duration,
+            title: tags.title,
+            artist: tags.artist,
+            album: tags.album,
+            track_number: tags.track_number,
+            album_artist: tags.album_artist,
+            duration: tags.duration,
+            replaygain: tags.replaygain,
+            loudness: None,
+            loudness_scanned: false,
+            lyrics: lyrics::load_lrc(path).or(tags.lyrics),
         });
     }
 
     tracks
 }
 
-fn extract_id3_tags(path: &Path) -> (String, String, String, Option<u32>, String, Option<u64>) {
+fn extract_id3_tags(path: &Path) -> TrackTags {
     let tag = Id3Tag::read_from_path(path).ok();
 
     let title = tag
@@ -414,86 +617,168 @@ fn extract_id3_tags(path: &Path) -> (String, String, String, Option<u32>, String
         .unwrap_or("Unknown Album Artist")
         .to_string();
 
-    let track_number = tag.and_then(|t| t.track()).map(|n| n as u32);
-
+    // ReplayGain is stored in TXXX frames, e.g. TXXX:REPLAYGAIN_TRACK_GAIN
+    let mut replaygain = ReplayGain::default();
+    for text in tag.iter().flat_map(|t| t.extended_texts()) {
+        replaygain.set_from_tag(&text.description, &text.value);
+    }
 
+    // Synced lyrics (SYLT) win over plain ones (USLT). SYLT timed in MPEG
+    // frames cannot be placed without decoding, so it is skipped.
+    let synced = tag
+        .iter()
+        .flat_map(|t| t.synchronised_lyrics())
+        .find(|sylt| sylt.timestamp_format == TimestampFormat::MS)
+        .and_then(|sylt| Lyrics::from_timed(&sylt.content));
+    let lyrics = synced.or_else(|| {
+        tag.iter()
+            .flat_map(|t| t.lyrics())
+            .find_map(|uslt| Lyrics::parse(&uslt.text))
+    });
+
+    let track_number = tag.and_then(|t| t.track());
+
+    TrackTags {
+        title,
+        artist,
+        album,
+        track_number,
+        album_artist,
+        duration: probe_duration(path),
+        replaygain,
+        lyrics,
+    }
+}
 
-    (title, artist, album, track_number, album_artist, None)
+/// Store ReplayGain values in the file's tags, replacing any already there:
+/// ID3 for MP3s, Vorbis comments for FLAC
+pub fn write_replaygain_tags(path: &Path, values: &ReplayGain) -> anyhow::Result<()> {
+    let gain = |db: f32| format!("{db:.2} dB");
+    let peak = |peak: f32| format!("{peak:.6}");
+    let entries: Vec<(&str, String)> = [
+        ("REPLAYGAIN_TRACK_GAIN", values.track_gain.map(gain)),
+        ("REPLAYGAIN_TRACK_PEAK", values.track_peak.map(peak)),
+        ("REPLAYGAIN_ALBUM_GAIN", values.album_gain.map(gain)),
+        ("REPLAYGAIN_ALBUM_PEAK", values.album_peak.map(peak)),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some((key, value?)))
+    .collect();
+
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|s| s.to_ascii_lowercase());
+
+    match ext.as_deref() {
+        Some("mp3") => write_id3_replaygain(path, entries),
+        Some("flac") => flac::set_comments(path, &entries),
+        _ => Err(anyhow::anyhow!("No tag format for {:?}", path)),
+    }
 }
 
-fn extract_symphonia_tags(path: &Path) -> (String, String, String, Option<u32>, String, Option<u64>) {
-    use symphonia::core::meta::StandardTagKey;
-
-    let file = match File::open(path) {
-        Ok(f) => f,
-        Err(_) => {
-            return (
-                "Unknown Title".into(),
-                "Unknown Artist".into(),
-                "Unknown Album".into(),
-                None,
-                "Unknown Album Artist".into(),
-                None,
-            );
-        }
+fn write_id3_replaygain(path: &Path, entries: Vec<(&str, String)>) -> anyhow::Result<()> {
+    let mut tag = match Id3Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(err) if matches!(err.kind, id3::ErrorKind::NoTag) => Id3Tag::new(),
+        Err(err) => return Err(err.into()),
     };
 
+    for (key, value) in entries {
+        // Other taggers use lower case keys; drop those too
+        tag.remove_extended_text(Some(key), None);
+        tag.remove_extended_text(Some(&key.to_ascii_lowercase()), None);
+        tag.add_frame(Frame::with_content(
+            "TXXX",
+            Content::ExtendedText(ExtendedText {
+                description: key.to_string(),
+                value,
+            }),
+        ));
+    }
+
+    // Keep the version the file had, as not every player reads ID3v2.4
+    let version = tag.version();
+    tag.write_to_path(path, version)?;
+    Ok(())
+}
+
+/// Open `path` with symphonia, `None` if it cannot be read
+fn probe_format(path: &Path) -> Option<Box<dyn FormatReader>> {
+    let file = File::open(path).ok()?;
     let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
-    let mut probed = match get_probe().format(
-        &Hint::new(),
-        mss,
-        &FormatOptions::default(),
-        &MetadataOptions::default(),
-    ) {
-        Ok(p) => p,
-        Err(_) => {
-            return (
-                "Unknown Title".into(),
-                "Unknown Artist".into(),
-                "Unknown Album".into(),
-                None,
-                "Unknown Album Artist".into(),
-                None,
-            );
-        }
+    let probed = get_probe()
+        .format(
+            &Hint::new(),
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+
+    Some(probed.format)
+}
+
+/// Length in whole seconds, from the stream header. MP3s without one get
+/// an estimate from the file size.
+fn stream_duration(format: &dyn FormatReader) -> Option<u64> {
+    let track = format.default_track()?;
+    let tb = track.codec_params.time_base?;
+    let n_frames = track.codec_params.n_frames?;
+
+    Some((n_frames * tb.numer as u64) / tb.denom as u64)
+}
+
+/// Length of a file whose tags are read some other way
+fn probe_duration(path: &Path) -> Option<u64> {
+    stream_duration(probe_format(path)?.as_ref())
+}
+
+fn extract_symphonia_tags(path: &Path) -> TrackTags {
+    let mut tags = TrackTags::default();
+
+    let Some(mut format) = probe_format(path) else {
+        return tags;
     };
 
-    let binding = probed.format.metadata();
+    let binding = format.metadata();
     let meta = binding.current();
 
-    let mut title = "Unknown Title".to_string();
-    let mut artist = "Unknown Artist".to_string();
-    let mut album_artist = "Unknown Album Artist".to_string();
-    let mut album = "Unknown Album".to_string();
-    let mut track_number = None;
-
     if let Some(m) = meta {
         for tag in m.tags() {
             match tag.std_key {
-                Some(StandardTagKey::TrackTitle) => title = tag.value.to_string(),
-                Some(StandardTagKey::Artist) => artist = tag.value.to_string(),
-                Some(StandardTagKey::AlbumArtist) => album_artist = tag.value.to_string(),
-                Some(StandardTagKey::Album) => album = tag.value.to_string(),
+                Some(StandardTagKey::TrackTitle) => tags.title = tag.value.to_string(),
+                Some(StandardTagKey::Artist) => tags.artist = tag.value.to_string(),
+                Some(StandardTagKey::AlbumArtist) => tags.album_artist = tag.value.to_string(),
+                Some(StandardTagKey::Album) => tags.album = tag.value.to_string(),
                 Some(StandardTagKey::TrackNumber) => {
-                    track_number = tag.value.to_string().parse::<u32>().ok();
+                    tags.track_number = tag.value.to_string().parse::<u32>().ok();
+                }
+                Some(StandardTagKey::ReplayGainTrackGain) => {
+                    tags.replaygain.track_gain = ReplayGain::parse_value(&tag.value.to_string());
+                }
+                Some(StandardTagKey::ReplayGainTrackPeak) => {
+                    tags.replaygain.track_peak = ReplayGain::parse_value(&tag.value.to_string());
+                }
+                Some(StandardTagKey::ReplayGainAlbumGain) => {
+                    tags.replaygain.album_gain = ReplayGain::parse_value(&tag.value.to_string());
+                }
+                Some(StandardTagKey::ReplayGainAlbumPeak) => {
+                    tags.replaygain.album_peak = ReplayGain::parse_value(&tag.value.to_string());
+                }
+                // LYRICS and UNSYNCEDLYRICS; either may hold LRC
+                Some(StandardTagKey::Lyrics) => {
+                    tags.lyrics = Lyrics::parse(&tag.value.to_string());
                 }
                 _ => {}
             }
         }
     }
 
-    let mut duration = None;
+    tags.duration = stream_duration(format.as_ref());
 
-    if let Some(track) = probed.format.default_track() {
-        if let Some(tb) = track.codec_params.time_base {
-            if let Some(n_frames) = track.codec_params.n_frames {
-                duration = Some((n_frames as u64 * tb.numer as u64) / tb.denom as u64);
-            }
-        }
-    }
-
-    (title, artist, album, track_number, album_artist, duration)
+    tags
 }
 
 #[derive(PartialEq)]
@@ -501,3 +786,85 @@ pub enum LibraryFocus {
     Left,
     Right,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaygain_values_parse() {
+        assert_eq!(ReplayGain::parse_value("-6.54 dB"), Some(-6.54));
+        assert_eq!(ReplayGain::parse_value("+2.10 db"), Some(2.1));
+        assert_eq!(ReplayGain::parse_value(" 3.5dB "), Some(3.5));
+        assert_eq!(ReplayGain::parse_value("0.988312"), Some(0.988312));
+        assert_eq!(ReplayGain::parse_value("loud"), None);
+        assert_eq!(ReplayGain::parse_value(""), None);
+    }
+
+    fn track(path: &str) -> LibraryTrack {
+        LibraryTrack {
+            path: PathBuf::from(path),
+            title: String::new(),
+            artist: "Artist".into(),
+            album: "Album".into(),
+            track_number: None,
+            album_artist: String::new(),
+            duration: None,
+            replaygain: ReplayGain::default(),
+            loudness: None,
+            loudness_scanned: false,
+            lyrics: None,
+        }
+    }
+
+    #[test]
+    fn scanned_tracks_without_loudness_are_not_scanned_again() {
+        let mut library = LibraryState::new();
+        library.artists.push(ArtistNode {
+            name: "Artist".into(),
+            albums: vec![AlbumNode {
+                name: "Album".into(),
+                tracks: vec![track("loud.mp3"), track("silent.mp3")],
+                loudness: None,
+            }],
+            expanded: false,
+        });
+
+        let loudness = Loudness {
+            integrated: -20.0,
+            range: 5.0,
+            true_peak: 0.9,
+        };
+        let results = [
+            (PathBuf::from("loud.mp3"), Some(loudness)),
+            (PathBuf::from("silent.mp3"), None),
+        ];
+        library.store_loudness("Artist", "Album", &results, None);
+
+        let tracks = &library.artists[0].albums[0].tracks;
+        assert_eq!(tracks[0].loudness, Some(loudness));
+        assert_eq!(tracks[1].loudness, None);
+        assert!(tracks.iter().all(|t| !t.needs_loudness_scan()));
+    }
+
+    #[test]
+    fn replaygain_tags_are_matched_by_name() {
+        let mut values = ReplayGain::default();
+
+        values.set_from_tag("replaygain_track_gain", "-7.00 dB");
+        values.set_from_tag("REPLAYGAIN_TRACK_PEAK", "0.5");
+        values.set_from_tag("ReplayGain_Album_Gain", "-8.00 dB");
+        values.set_from_tag("REPLAYGAIN_ALBUM_PEAK", "0.75");
+        values.set_from_tag("REPLAYGAIN_REFERENCE_LOUDNESS", "89 dB");
+
+        assert_eq!(
+            values,
+            ReplayGain {
+                track_gain: Some(-7.0),
+                track_peak: Some(0.5),
+                album_gain: Some(-8.0),
+                album_peak: Some(0.75),
+            }
+        );
+    }
+}
@@ -0,0 +1,284 @@
+mod r128;
+
+use std::path::{Path, PathBuf};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use std::thread::{self, JoinHandle};
+
+use symphonia::core::errors::Error as SymphoniaError;
+
+use serde::{Deserialize, Serialize};
+
+use crate::library::{LibraryState, LibraryTrack, ReplayGain, write_replaygain_tags};
+use crate::persistence;
+use crate::player::{interleave, open_decoder};
+
+use r128::{Measurement, R128Meter};
+
+/// Loudness ReplayGain 2.0 brings every track to
+const REFERENCE_LUFS: f64 = -18.0;
+
+/// EBU R128 measurement of a track or a whole album
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Loudness {
+    /// Integrated loudness in LUFS
+    pub integrated: f32,
+
+    /// Loudness range in LU
+    pub range: f32,
+
+    /// Highest inter-sample peak, linear, 1.0 being full scale
+    pub true_peak: f32,
+}
+
+impl Loudness {
+    /// `None` for silence, or anything too quiet to pass the gates, which
+    /// has no loudness to correct
+    fn from_measurement(measurement: &Measurement) -> Option<Self> {
+        Some(Self {
+            integrated: measurement.integrated()? as f32,
+            range: measurement.range() as f32,
+            true_peak: measurement.true_peak,
+        })
+    }
+
+    /// Gain that brings this to the ReplayGain 2.0 reference, in dB
+    pub fn replaygain_db(&self) -> f32 {
+        REFERENCE_LUFS as f32 - self.integrated
+    }
+}
+
+/// Counts shared between the scan thread and the UI
+#[derive(Default)]
+struct Progress {
+    total: AtomicUsize,
+    done: AtomicUsize,
+    failed: AtomicUsize,
+    finished: AtomicBool,
+}
+
+/// Background job measuring the loudness of every library album that has
+/// not been measured yet, one album at a time. Results are stored in the
+/// library and saved to `library.json` after each album.
+pub struct LoudnessScan {
+    progress: Arc<Progress>,
+    cancel: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+
+    /// Progress as last returned by `progress_update`
+    reported: Option<(usize, usize)>,
+}
+
+impl LoudnessScan {
+    /// Start scanning. With `write_tags`, MP3 and FLAC files also get the
+    /// results as ReplayGain tags.
+    pub fn spawn(library: Arc<Mutex<LibraryState>>, write_tags: bool) -> Self {
+        let progress = Arc::new(Progress::default());
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let thread_progress = Arc::clone(&progress);
+        let thread_cancel = Arc::clone(&cancel);
+        let handle = thread::spawn(move || {
+            scan(&library, write_tags, &thread_progress, &thread_cancel);
+            thread_progress.finished.store(true, Ordering::SeqCst);
+        });
+
+        Self {
+            progress,
+            cancel,
+            handle: Some(handle),
+            reported: None,
+        }
+    }
+
+    /// Tracks measured so far, out of how many
+    pub fn progress(&self) -> (usize, usize) {
+        (
+            self.progress.done.load(Ordering::Relaxed),
+            self.progress.total.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Like `progress`, but `None` if it has not moved since the last call
+    pub fn progress_update(&mut self) -> Option<(usize, usize)> {
+        let progress = self.progress();
+        if self.reported == Some(progress) {
+            return None;
+        }
+
+        self.reported = Some(progress);
+        Some(progress)
+    }
+
+    /// Tracks that could not be decoded
+    pub fn failed(&self) -> usize {
+        self.progress.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress.finished.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for LoudnessScan {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take()
+            && handle.join().is_err()
+        {
+            log::error!("Loudness scan panicked");
+        }
+    }
+}
+
+/// An album to measure, found by name again once its tracks are done
+struct AlbumJob {
+    artist: String,
+    album: String,
+    paths: Vec<PathBuf>,
+}
+
+fn scan(library: &Mutex<LibraryState>, write_tags: bool, progress: &Progress, cancel: &AtomicBool) {
+    // Album loudness needs every track, so an album is measured again as a
+    // whole if any part of it has not been scanned
+    let jobs: Vec<AlbumJob> = {
+        let lib = library.lock().unwrap();
+        lib.artists
+            .iter()
+            .flat_map(|artist| artist.albums.iter().map(move |album| (artist, album)))
+            .filter(|(_, album)| album.tracks.iter().any(LibraryTrack::needs_loudness_scan))
+            .map(|(artist, album)| AlbumJob {
+                artist: artist.name.clone(),
+                album: album.name.clone(),
+                paths: album.tracks.iter().map(|t| t.path.clone()).collect(),
+            })
+            .collect()
+    };
+
+    let total = jobs.iter().map(|job| job.paths.len()).sum();
+    progress.total.store(total, Ordering::Relaxed);
+    log::info!("Loudness scan: {} albums, {total} tracks", jobs.len());
+
+    for job in jobs {
+        let mut album = Measurement::default();
+        let mut complete = true;
+
+        // Silent and unreadable tracks are recorded as scanned with no
+        // loudness, so later scans leave them alone
+        let mut tracks = Vec::new();
+
+        for path in &job.paths {
+            match measure(path, cancel) {
+                Ok(Some(measurement)) => {
+                    album.append(&measurement);
+                    tracks.push((path.clone(), Loudness::from_measurement(&measurement)));
+                }
+                Ok(None) => return,
+                Err(err) => {
+                    log::warn!("Could not measure {:?}: {err}", path);
+                    progress.failed.fetch_add(1, Ordering::Relaxed);
+                    tracks.push((path.clone(), None));
+                    complete = false;
+                }
+            }
+            progress.done.fetch_add(1, Ordering::Relaxed);
+        }
+
+        // An album with a track missing would get the wrong album gain
+        let album_loudness = (complete && !tracks.is_empty())
+            .then(|| Loudness::from_measurement(&album))
+            .flatten();
+
+        let mut written = Vec::new();
+        if write_tags {
+            for (path, loudness) in &tracks {
+                // Tracks without a loudness keep whatever tags they have
+                let Some(loudness) = loudness else {
+                    continue;
+                };
+
+                let values = replaygain_values(loudness, album_loudness.as_ref());
+                match write_replaygain_tags(path, &values) {
+                    Ok(()) => written.push((path.clone(), values)),
+                    Err(err) => log::warn!("Could not write ReplayGain tags to {:?}: {err}", path),
+                }
+            }
+        }
+
+        let mut lib = library.lock().unwrap();
+        lib.store_loudness(&job.artist, &job.album, &tracks, album_loudness);
+        for (path, values) in written {
+            lib.set_replaygain_tags(&path, values);
+        }
+
+        if let Err(err) = persistence::save_library(&lib.artists) {
+            log::error!("Could not save library: {err}");
+        }
+    }
+}
+
+/// ReplayGain tag values for a measured track
+fn replaygain_values(track: &Loudness, album: Option<&Loudness>) -> ReplayGain {
+    ReplayGain {
+        track_gain: Some(track.replaygain_db()),
+        track_peak: Some(track.true_peak),
+        album_gain: album.map(Loudness::replaygain_db),
+        album_peak: album.map(|album| album.true_peak),
+    }
+}
+
+/// Decode the whole file and measure it. `None` if cancelled part way.
+fn measure(path: &Path, cancel: &AtomicBool) -> anyhow::Result<Option<Measurement>> {
+    let (mut format, mut decoder, track) = open_decoder(path)?;
+
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or_else(|| anyhow::anyhow!("Unknown sample rate"))?;
+
+    let mut meter: Option<R128Meter> = None;
+    let mut samples = Vec::new();
+
+    loop {
+        if cancel.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(err))
+                if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                break;
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        if packet.track_id() != track.id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(err)) => {
+                log::debug!("Skipping bad packet in {:?}: {err}", path);
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        // The channel count is only certain once a packet is decoded
+        let channels = decoded.spec().channels.count();
+        let meter = meter.get_or_insert_with(|| R128Meter::new(sample_rate, channels));
+
+        samples.clear();
+        if interleave(decoded, &mut samples) {
+            meter.process(&samples);
+        }
+    }
+
+    let meter = meter.ok_or_else(|| anyhow::anyhow!("No audio decoded"))?;
+    Ok(Some(meter.finish()))
+}
@@ -0,0 +1,441 @@
+use std::f64::consts::PI;
+
+/// Gating block and step, in 100 ms steps: momentary blocks are 400 ms long
+/// and short-term windows 3 s, both advancing 100 ms at a time
+const BLOCK_STEPS: usize = 4;
+const SHORT_TERM_STEPS: usize = 30;
+
+/// Blocks quieter than this never count
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+
+/// Relative gates below the ungated mean, for integrated loudness and for
+/// loudness range
+const INTEGRATED_GATE_LU: f64 = -10.0;
+const RANGE_GATE_LU: f64 = -20.0;
+
+/// Percentiles of short-term loudness whose distance is the loudness range
+const RANGE_LOW: f64 = 0.10;
+const RANGE_HIGH: f64 = 0.95;
+
+/// True peak oversampling, and taps of the interpolation filter per phase
+const OVERSAMPLE: usize = 4;
+const PHASE_TAPS: usize = 12;
+
+/// Loudness of a mean square energy, in LUFS
+pub fn loudness_of(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// Everything needed to work out the loudness of one track or, with the
+/// measurements of its other tracks appended, of a whole album
+#[derive(Debug, Clone, Default)]
+pub struct Measurement {
+    /// Mean square energy of each 400 ms block
+    pub blocks: Vec<f64>,
+
+    /// Mean square energy of each 3 s window
+    pub short_term: Vec<f64>,
+
+    /// Highest inter-sample peak, linear
+    pub true_peak: f32,
+}
+
+impl Measurement {
+    pub fn append(&mut self, other: &Measurement) {
+        self.blocks.extend_from_slice(&other.blocks);
+        self.short_term.extend_from_slice(&other.short_term);
+        self.true_peak = self.true_peak.max(other.true_peak);
+    }
+
+    /// Gated integrated loudness in LUFS, or `None` for silence
+    pub fn integrated(&self) -> Option<f64> {
+        let audible = gated(&self.blocks, ABSOLUTE_GATE_LUFS);
+        let relative_gate = loudness_of(mean(&audible)?) + INTEGRATED_GATE_LU;
+
+        let counted = gated(&audible, relative_gate);
+        mean(&counted).map(loudness_of)
+    }
+
+    /// Spread between quiet and loud passages in LU (EBU Tech 3342)
+    pub fn range(&self) -> f64 {
+        let audible = gated(&self.short_term, ABSOLUTE_GATE_LUFS);
+        let Some(ungated) = mean(&audible) else {
+            return 0.0;
+        };
+
+        let relative_gate = loudness_of(ungated) + RANGE_GATE_LU;
+        let mut counted: Vec<f64> = gated(&audible, relative_gate)
+            .into_iter()
+            .map(loudness_of)
+            .collect();
+        if counted.is_empty() {
+            return 0.0;
+        }
+
+        counted.sort_by(f64::total_cmp);
+        let percentile = |p: f64| counted[((counted.len() - 1) as f64 * p).round() as usize];
+
+        percentile(RANGE_HIGH) - percentile(RANGE_LOW)
+    }
+}
+
+/// Energies louder than `gate` LUFS
+fn gated(energies: &[f64], gate: f64) -> Vec<f64> {
+    energies
+        .iter()
+        .copied()
+        .filter(|&energy| energy > 0.0 && loudness_of(energy) > gate)
+        .collect()
+}
+
+fn mean(energies: &[f64]) -> Option<f64> {
+    (!energies.is_empty()).then(|| energies.iter().sum::<f64>() / energies.len() as f64)
+}
+
+/// Second order IIR section, transposed direct form II
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The two K-weighting stages of ITU-R BS.1770 at any sample rate: a high
+/// shelf for the head's acoustics, then a high pass
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain_db = 3.999843853973347;
+    let q = 0.7071752369554196;
+
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    [shelf, high_pass]
+}
+
+/// How much each channel counts: surrounds a bit more, LFE not at all.
+/// Only 5.1 is told apart; everything else counts every channel once.
+fn channel_weights(channels: usize) -> Vec<f64> {
+    if channels == 6 {
+        vec![1.0, 1.0, 1.0, 0.0, 1.41, 1.41]
+    } else {
+        vec![1.0; channels]
+    }
+}
+
+/// Measures one stream of interleaved samples at a fixed rate and channel
+/// count, as BS.1770 and EBU R128 describe
+pub struct R128Meter {
+    channels: usize,
+    weights: Vec<f64>,
+    filters: Vec<[Biquad; 2]>,
+
+    /// Frames per 100 ms step, and how far into the current step we are
+    step_frames: usize,
+    step_pos: usize,
+    step_energy: f64,
+
+    /// Weighted energy of the most recent steps, newest last
+    steps: Vec<f64>,
+
+    true_peak: TruePeak,
+    measurement: Measurement,
+}
+
+impl R128Meter {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let channels = channels.max(1);
+
+        Self {
+            channels,
+            weights: channel_weights(channels),
+            filters: vec![k_weighting(sample_rate); channels],
+            step_frames: (sample_rate as usize / 10).max(1),
+            step_pos: 0,
+            step_energy: 0.0,
+            steps: Vec::with_capacity(SHORT_TERM_STEPS),
+            true_peak: TruePeak::new(channels),
+            measurement: Measurement::default(),
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) {
+        self.true_peak.process(samples);
+
+        for frame in samples.chunks_exact(self.channels) {
+            for ((sample, filter), weight) in frame.iter().zip(&mut self.filters).zip(&self.weights) {
+                let [shelf, high_pass] = filter;
+                let weighted = high_pass.process(shelf.process(*sample as f64));
+                self.step_energy += weight * weighted * weighted;
+            }
+
+            self.step_pos += 1;
+            if self.step_pos == self.step_frames {
+                self.end_step();
+            }
+        }
+    }
+
+    fn end_step(&mut self) {
+        if self.steps.len() == SHORT_TERM_STEPS {
+            self.steps.remove(0);
+        }
+        self.steps.push(self.step_energy / self.step_frames as f64);
+        self.step_pos = 0;
+        self.step_energy = 0.0;
+
+        let energy_of_last = |count: usize| {
+            let last = &self.steps[self.steps.len() - count..];
+            last.iter().sum::<f64>() / count as f64
+        };
+
+        if self.steps.len() >= BLOCK_STEPS {
+            let block = energy_of_last(BLOCK_STEPS);
+            self.measurement.blocks.push(block);
+        }
+        if self.steps.len() == SHORT_TERM_STEPS {
+            let window = energy_of_last(SHORT_TERM_STEPS);
+            self.measurement.short_term.push(window);
+        }
+    }
+
+    /// What was measured; a partial last step is left out
+    pub fn finish(mut self) -> Measurement {
+        self.measurement.true_peak = self.true_peak.peak;
+        self.measurement
+    }
+}
+
+/// Inter-sample peak level, found by interpolating four points between each
+/// pair of samples with a windowed sinc
+struct TruePeak {
+    channels: usize,
+
+    /// Filter taps of each phase, newest sample first
+    phases: Vec<[f32; PHASE_TAPS]>,
+
+    /// Last `PHASE_TAPS` samples of each channel, and where the next goes
+    history: Vec<[f32; PHASE_TAPS]>,
+    pos: usize,
+
+    peak: f32,
+}
+
+impl TruePeak {
+    fn new(channels: usize) -> Self {
+        let len = OVERSAMPLE * PHASE_TAPS;
+        let centre = (len - 1) as f64 / 2.0;
+
+        let phases = (0..OVERSAMPLE)
+            .map(|phase| {
+                let mut taps = [0.0; PHASE_TAPS];
+                for (k, tap) in taps.iter_mut().enumerate() {
+                    let n = (k * OVERSAMPLE + phase) as f64;
+                    let x = (n - centre) / OVERSAMPLE as f64;
+                    let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                    let window = 0.5 - 0.5 * (2.0 * PI * (n + 0.5) / len as f64).cos();
+                    *tap = (sinc * window) as f32;
+                }
+                taps
+            })
+            .collect();
+
+        Self {
+            channels,
+            phases,
+            history: vec![[0.0; PHASE_TAPS]; channels],
+            pos: 0,
+            peak: 0.0,
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (sample, history) in frame.iter().zip(&mut self.history) {
+                history[self.pos] = *sample;
+                self.peak = self.peak.max(sample.abs());
+
+                for taps in &self.phases {
+                    let mut sum = 0.0;
+                    for (k, tap) in taps.iter().enumerate() {
+                        sum += tap * history[(self.pos + PHASE_TAPS - k) % PHASE_TAPS];
+                    }
+                    self.peak = self.peak.max(sum.abs());
+                }
+            }
+
+            self.pos = (self.pos + 1) % PHASE_TAPS;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Low, to keep the tests quick; 1 kHz is well inside it
+    const RATE: u32 = 16000;
+
+    /// Stereo 1 kHz sine with the given peak level, which BS.1770 puts at
+    /// that many LUFS
+    fn tone(level: f64, secs: f64) -> Vec<f32> {
+        let amplitude = 10f64.powf(level / 20.0);
+        let frames = (RATE as f64 * secs) as usize;
+
+        (0..frames)
+            .flat_map(|i| {
+                let x = (amplitude * (2.0 * PI * 1000.0 * i as f64 / RATE as f64).sin()) as f32;
+                [x, x]
+            })
+            .collect()
+    }
+
+    /// Stereo white noise with the given RMS level in dBFS
+    fn noise(level: f64, secs: f64) -> Vec<f32> {
+        let rms = 10f64.powf(level / 20.0);
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+
+        (0..(RATE as f64 * secs) as usize * 2)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let uniform = (state >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0;
+                (uniform * rms * 3f64.sqrt()) as f32
+            })
+            .collect()
+    }
+
+    fn measure(parts: &[Vec<f32>]) -> Measurement {
+        let mut meter = R128Meter::new(RATE, 2);
+        for part in parts {
+            for block in part.chunks(4096) {
+                meter.process(block);
+            }
+        }
+        meter.finish()
+    }
+
+    fn assert_near(value: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (value - expected).abs() <= tolerance,
+            "{value} not within {tolerance} of {expected}"
+        );
+    }
+
+    #[test]
+    fn sine_at_reference_level() {
+        let measurement = measure(&[tone(-23.0, 10.0)]);
+        assert_near(measurement.integrated().unwrap(), -23.0, 0.1);
+    }
+
+    #[test]
+    fn noise_follows_its_level() {
+        let quiet = measure(&[noise(-30.0, 10.0)]).integrated().unwrap();
+        let loud = measure(&[noise(-20.0, 10.0)]).integrated().unwrap();
+
+        assert_near(loud - quiet, 10.0, 0.1);
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        let silence = vec![0.0; RATE as usize * 2 * 5];
+        assert_eq!(measure(&[silence]).integrated(), None);
+
+        // Below the absolute gate
+        assert_eq!(measure(&[tone(-80.0, 5.0)]).integrated(), None);
+    }
+
+    #[test]
+    fn silence_is_gated_out() {
+        let silence = vec![0.0; RATE as usize * 2 * 10];
+        let measurement = measure(&[tone(-23.0, 10.0), silence]);
+
+        assert_near(measurement.integrated().unwrap(), -23.0, 0.1);
+    }
+
+    #[test]
+    fn quiet_passages_are_gated_out() {
+        // 20 LU under the loud part, so under the relative gate
+        let measurement = measure(&[tone(-20.0, 10.0), tone(-40.0, 10.0)]);
+
+        assert_near(measurement.integrated().unwrap(), -20.0, 0.2);
+    }
+
+    #[test]
+    fn range_of_alternating_levels() {
+        let parts: Vec<_> = (0..6)
+            .map(|i| tone(if i % 2 == 0 { -20.0 } else { -30.0 }, 10.0))
+            .collect();
+        let measurement = measure(&parts);
+
+        assert_near(measurement.range(), 10.0, 0.5);
+    }
+
+    #[test]
+    fn steady_tone_has_no_range() {
+        assert_near(measure(&[tone(-23.0, 20.0)]).range(), 0.0, 0.1);
+    }
+
+    #[test]
+    fn album_is_measured_over_all_tracks() {
+        let mut album = measure(&[tone(-20.0, 10.0)]);
+        album.append(&measure(&[tone(-26.0, 10.0)]));
+
+        // Mean energy of the two levels, both within the relative gate
+        let expected = 10.0 * ((10f64.powf(-2.0) + 10f64.powf(-2.6)) / 2.0).log10();
+        assert_near(album.integrated().unwrap(), expected, 0.1);
+    }
+
+    #[test]
+    fn true_peak_between_samples() {
+        // A quarter of the sample rate, sampled 45 degrees off its peaks,
+        // so no sample gets above 0.707
+        let samples: Vec<f32> = (0..RATE as usize)
+            .map(|i| (PI / 2.0 * i as f64 + PI / 4.0).sin() as f32)
+            .collect();
+        let sample_peak = samples.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+
+        let mut meter = R128Meter::new(RATE, 1);
+        meter.process(&samples);
+        let true_peak = meter.finish().true_peak;
+
+        assert!(sample_peak < 0.71);
+        assert_near(true_peak as f64, 1.0, 0.02);
+    }
+}
@@ -0,0 +1,316 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// A line of synced lyrics and when it starts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LyricLine {
+    pub at: Duration,
+    pub text: String,
+}
+
+/// Lyrics of a track, from an `.lrc` file next to it or from its tags
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Lyrics {
+    /// Lines with timestamps, in playing order
+    Synced(Vec<LyricLine>),
+
+    /// Plain text, one entry per line
+    Unsynced(Vec<String>),
+}
+
+impl Lyrics {
+    /// Parse LRC, e.g. `[01:23.45]Some words`. Text without any timestamps
+    /// is taken as unsynced lyrics. `None` if there is nothing to show.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim_start_matches('\u{feff}');
+
+        let mut synced = Vec::new();
+        let mut plain = Vec::new();
+        let mut offset_ms = 0i64;
+
+        for line in text.lines() {
+            let mut rest = line.trim();
+            let mut times = Vec::new();
+            let mut is_tag = false;
+
+            // A line can start with several timestamps, or be an ID tag like
+            // [ar:Artist]
+            while let Some(tag) = rest.strip_prefix('[')
+                && let Some((inside, after)) = tag.split_once(']')
+            {
+                if let Some(time) = parse_timestamp(inside) {
+                    times.push(time);
+                } else if let Some(offset) = inside.strip_prefix("offset:") {
+                    offset_ms = offset.trim().parse().unwrap_or(0);
+                    is_tag = true;
+                } else if inside.contains(':') {
+                    is_tag = true;
+                } else {
+                    break;
+                }
+                rest = after;
+            }
+
+            if is_tag && times.is_empty() {
+                continue;
+            }
+
+            let text = strip_word_times(rest);
+            if times.is_empty() {
+                plain.push(text);
+            } else {
+                synced.extend(times.into_iter().map(|at| (at, text.clone())));
+            }
+        }
+
+        if !synced.is_empty() {
+            // A positive offset shows the lyrics sooner
+            let lines = synced
+                .into_iter()
+                .map(|(at, text): (i64, String)| LyricLine {
+                    at: Duration::from_millis((at - offset_ms).max(0) as u64),
+                    text,
+                })
+                .collect();
+            return Some(Self::synced(lines));
+        }
+
+        Self::unsynced(plain)
+    }
+
+    /// From ID3 SYLT entries timed in milliseconds
+    pub fn from_timed(entries: &[(u32, String)]) -> Option<Self> {
+        let lines: Vec<LyricLine> = entries
+            .iter()
+            .map(|(ms, text)| LyricLine {
+                at: Duration::from_millis(*ms as u64),
+                text: text.trim().to_string(),
+            })
+            .collect();
+
+        (!lines.is_empty()).then(|| Self::synced(lines))
+    }
+
+    fn synced(mut lines: Vec<LyricLine>) -> Self {
+        lines.sort_by_key(|line| line.at);
+        Self::Synced(lines)
+    }
+
+    /// `None` if every line is empty
+    fn unsynced(mut lines: Vec<String>) -> Option<Self> {
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        let first = lines.iter().position(|line| !line.is_empty())?;
+        lines.drain(..first);
+
+        Some(Self::Unsynced(lines))
+    }
+
+    pub fn is_synced(&self) -> bool {
+        matches!(self, Self::Synced(_))
+    }
+
+    /// Index of the line being sung at `position`, `None` before the first
+    /// one or for unsynced lyrics
+    pub fn current_line(&self, position: Duration) -> Option<usize> {
+        match self {
+            Self::Synced(lines) => lines
+                .partition_point(|line| line.at <= position)
+                .checked_sub(1),
+            Self::Unsynced(_) => None,
+        }
+    }
+
+    /// The text of every line, in order
+    pub fn lines(&self) -> Vec<&str> {
+        match self {
+            Self::Synced(lines) => lines.iter().map(|line| line.text.as_str()).collect(),
+            Self::Unsynced(lines) => lines.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// Lyrics from an `.lrc` file with the same name as the track, if any
+pub fn load_lrc(track: &Path) -> Option<Lyrics> {
+    let path = track.with_extension("lrc");
+    let bytes = fs::read(&path).ok()?;
+
+    let lyrics = Lyrics::parse(&String::from_utf8_lossy(&bytes));
+    if lyrics.is_none() {
+        log::debug!("No lyrics in {:?}", path);
+    }
+    lyrics
+}
+
+/// `mm:ss`, `mm:ss.xx` or `mm:ss:xx` in milliseconds
+fn parse_timestamp(text: &str) -> Option<i64> {
+    let (minutes, rest) = text.split_once(':')?;
+    let minutes: i64 = minutes.trim().parse().ok()?;
+
+    let (seconds, fraction) = match rest.split_once(['.', ':']) {
+        Some((seconds, fraction)) => (seconds, fraction),
+        None => (rest, ""),
+    };
+    let seconds: i64 = seconds.trim().parse().ok()?;
+
+    // Hundredths usually, but milliseconds and tenths turn up too
+    let fraction = fraction.trim();
+    let millis = if fraction.is_empty() {
+        0
+    } else {
+        let digits: i64 = fraction.parse().ok()?;
+        match fraction.len() {
+            1 => digits * 100,
+            2 => digits * 10,
+            3 => digits,
+            _ => return None,
+        }
+    };
+
+    Some(minutes * 60_000 + seconds * 1000 + millis)
+}
+
+/// Drop the per-word timestamps of enhanced LRC, `<01:23.45>`
+fn strip_word_times(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        let Some(len) = rest[start..].find('>') else {
+            break;
+        };
+
+        result.push_str(&rest[..start]);
+        if parse_timestamp(&rest[start + 1..start + len]).is_none() {
+            result.push_str(&rest[start..=start + len]);
+        }
+        rest = &rest[start + len + 1..];
+    }
+
+    result.push_str(rest);
+    result.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synced(text: &str) -> Vec<(u64, String)> {
+        match Lyrics::parse(text) {
+            Some(Lyrics::Synced(lines)) => lines
+                .into_iter()
+                .map(|line| (line.at.as_millis() as u64, line.text))
+                .collect(),
+            other => panic!("expected synced lyrics, got {other:?}"),
+        }
+    }
+
+    fn line(ms: u64, text: &str) -> (u64, String) {
+        (ms, text.to_string())
+    }
+
+    #[test]
+    fn timestamps_and_fractions() {
+        let lines = synced("[00:01]a\n[00:02.5]b\n[00:03.25]c\n[01:04.125]d\n[00:05:50]e");
+
+        // Sorted by time, whatever the order in the file
+        assert_eq!(
+            lines,
+            vec![
+                line(1000, "a"),
+                line(2500, "b"),
+                line(3250, "c"),
+                line(5500, "e"),
+                line(64125, "d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn several_timestamps_on_one_line() {
+        let lines = synced("[00:10.00][00:30.00]Chorus\n[00:20.00]Verse");
+
+        assert_eq!(
+            lines,
+            vec![
+                line(10_000, "Chorus"),
+                line(20_000, "Verse"),
+                line(30_000, "Chorus")
+            ]
+        );
+    }
+
+    #[test]
+    fn positive_offset_shows_lines_sooner() {
+        let lines = synced("[offset:+500]\n[00:01.00]a\n[00:00.20]b");
+
+        // Clamped at the start of the track
+        assert_eq!(lines, vec![line(0, "b"), line(500, "a")]);
+    }
+
+    #[test]
+    fn negative_offset_shows_lines_later() {
+        let lines = synced("[offset:-250]\n[00:01.00]a");
+        assert_eq!(lines, vec![line(1250, "a")]);
+    }
+
+    #[test]
+    fn id_tags_are_skipped() {
+        let lines = synced("[ar:Artist]\n[ti:Title]\n[length:03:15]\n[00:01.00]a");
+        assert_eq!(lines, vec![line(1000, "a")]);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let text = "[00:01.00]good\n[00:xx.00]bad time\n[00:02.0000]too precise\n\
+                    [00:03.00 unclosed\nno timestamp\n[00:04.00]";
+        let lines = synced(text);
+
+        // Untimed text has no place in synced lyrics; an empty timed line
+        // is a pause
+        assert_eq!(lines, vec![line(1000, "good"), line(4000, "")]);
+    }
+
+    #[test]
+    fn enhanced_word_times_are_dropped() {
+        let lines = synced("[00:01.00]<00:01.00>Hello <00:01.50>world <b>");
+        assert_eq!(lines, vec![line(1000, "Hello world <b>")]);
+    }
+
+    #[test]
+    fn plain_text_is_unsynced() {
+        let lyrics = Lyrics::parse("\u{feff}\n\nFirst line\n\nSecond line\n\n").unwrap();
+
+        assert!(!lyrics.is_synced());
+        assert_eq!(lyrics.lines(), vec!["First line", "", "Second line"]);
+        assert_eq!(lyrics.current_line(Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn nothing_to_show() {
+        assert_eq!(Lyrics::parse(""), None);
+        assert_eq!(Lyrics::parse("[ar:Artist]\n[ti:Title]\n\n"), None);
+        assert_eq!(Lyrics::from_timed(&[]), None);
+    }
+
+    #[test]
+    fn current_line_follows_position() {
+        let lyrics = Lyrics::parse("[00:01.00]a\n[00:02.00]b\n[00:03.00]c").unwrap();
+        let at = |ms| lyrics.current_line(Duration::from_millis(ms));
+
+        assert_eq!(at(500), None);
+        assert_eq!(at(1000), Some(0));
+        assert_eq!(at(2999), Some(1));
+        assert_eq!(at(60_000), Some(2));
+    }
+
+    #[test]
+    fn timed_entries_are_sorted_and_trimmed() {
+        let lyrics = Lyrics::from_timed(&[(2000, " b ".into()), (1000, "a\n".into())]).unwrap();
+        assert_eq!(lyrics.lines(), vec!["a", "b"]);
+    }
+}
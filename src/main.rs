@@ -1,25 +1,27 @@
 mod app;
 mod browser;
+mod equalizer;
+mod flac;
 mod library;
 mod list;
+mod loudness;
+mod lyrics;
+mod meters;
 mod persistence;
 mod player;
 mod screens;
+mod settings;
 mod ui;
+mod visualizer;
 
 use app::{App, AppScreen};
 
-use std::time::Instant;
-
 use crate::browser::BrowserItem;
 
 use crate::library::{LibraryFocus, scan_path_for_tracks};
 
-use std::sync::Arc;
-use std::sync::atomic::Ordering;
-
 use crossterm::{
-    event::{self, Event, KeyCode},
+    event::{self, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
@@ -37,6 +39,15 @@ fn main() -> Result<()> {
     )])
     .unwrap();
 
+    // Put the terminal back before the panic message is printed, so a crash
+    // does not leave the shell in raw mode
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = disable_raw_mode();
+        let _ = execute!(stdout(), LeaveAlternateScreen);
+        default_hook(info);
+    }));
+
     enable_raw_mode()?;
     execute!(stdout(), EnterAlternateScreen)?;
 
@@ -48,48 +59,38 @@ fn main() -> Result<()> {
     loop {
         app.update();
 
-        if app
-            .player
-            .lock()
-            .unwrap()
-            .autoplay_trigger
-            .swap(false, Ordering::SeqCst)
-        {
-            let mut plyr = app.player.lock().unwrap();
-            let mut lib = app.library.lock().unwrap();
-
-            if let Some(current_path) = &plyr.current_path {
-                if app.autoplay_enabled {
-                    if let Some(next_path) = lib.next_track_path(current_path) {
-                        lib.select_track_by_path(&next_path);
-                        plyr.play(&next_path);
-
-                        if let Some(next_track) = lib.track_by_path(&next_path) {
-                            app.current_track = Some(next_track.clone());
-                            app.playback_start = Some(Instant::now());
-
-                            log::debug!(
-                                "Autoplay switched to: {} – {}",
-                                next_track.album_artist,
-                                next_track.title
-                            );
-                            log::debug!("playback_start: {:?}", app.playback_start);
-                        }
-                    }
-                }
-            }
-        }
-
         log::debug!("Drawing track: {:?}", app.current_track.as_ref().map(|t| &t.title));
         terminal.draw(|f| ui::draw_ui(f, &mut app))?;
 
-        if event::poll(std::time::Duration::from_millis(200))? {
+        // The spectrum and the level meters need a higher frame rate than
+        // the rest of the UI
+        let tick = if app.screen == AppScreen::Visualizer {
+            33
+        } else if app.current_track.is_some() {
+            100
+        } else {
+            200
+        };
+
+        if event::poll(std::time::Duration::from_millis(tick))? {
 
             if let Event::Key(key) = event::read()? {
                 match key.code {
                     KeyCode::Char('q') => break,
+
+                    // Alt+0..9 jumps to 0%..90% of the current track
+                    KeyCode::Char(c @ '0'..='9') if key.modifiers.contains(KeyModifiers::ALT) => {
+                        let tenths = c.to_digit(10).unwrap_or(0);
+                        app.seek_fraction(tenths as f64 / 10.0);
+                    }
+
                     KeyCode::Char('1') => app.goto_screen(app::AppScreen::Library),
                     KeyCode::Char('5') => app.goto_screen(app::AppScreen::Browser),
+                    KeyCode::Char('6') => app.goto_screen(app::AppScreen::Equalizer),
+                    KeyCode::Char('7') => app.goto_screen(app::AppScreen::Dsp),
+                    KeyCode::Char('8') => app.goto_screen(app::AppScreen::Output),
+                    KeyCode::Char('9') => app.goto_screen(app::AppScreen::Visualizer),
+                    KeyCode::Char('0') => app.goto_screen(app::AppScreen::Lyrics),
                     KeyCode::Char('a') => {
                         let mut lib = app.library_mut();
 
@@ -118,6 +119,16 @@ fn main() -> Result<()> {
                                 }
                             }
                         },
+
+                        AppScreen::Equalizer => {
+                            app.equalizer.move_down();
+                            app.apply_eq();
+                        }
+
+                        AppScreen::Dsp => app.dsp.move_down(),
+                        AppScreen::Output => app.devices.move_down(),
+                        AppScreen::Visualizer => app.change_visualizer_bars(false),
+                        AppScreen::Lyrics => app.scroll_lyrics(true),
                     },
 
                     KeyCode::Up => match app.screen {
@@ -131,6 +142,16 @@ fn main() -> Result<()> {
                                 LibraryFocus::Right => lib.move_track_up(),
                             }
                         },
+
+                        AppScreen::Equalizer => {
+                            app.equalizer.move_up();
+                            app.apply_eq();
+                        }
+
+                        AppScreen::Dsp => app.dsp.move_up(),
+                        AppScreen::Output => app.devices.move_up(),
+                        AppScreen::Visualizer => app.change_visualizer_bars(true),
+                        AppScreen::Lyrics => app.scroll_lyrics(false),
                     },
 
                     KeyCode::Enter => {
@@ -138,7 +159,16 @@ fn main() -> Result<()> {
                             app.browser.open_selected();
                         }
 
-                        let mut lib = app.library_mut();
+                        if app.screen == AppScreen::Equalizer {
+                            app.equalizer.apply_selected_preset();
+                            app.apply_eq();
+                        }
+
+                        if app.screen == AppScreen::Output {
+                            app.select_output_device();
+                        }
+
+                        let lib = app.library_mut();
 
                         if app.screen == AppScreen::Library
                             && lib.focus == LibraryFocus::Right
@@ -149,24 +179,13 @@ fn main() -> Result<()> {
                             };
 
                             if let Some(track) = selected {
-                                let player = Arc::clone(&app.player);
-
-                                // Stop current playback and play selected track
-                                {
-                                    let mut plyr = player.lock().unwrap();
-                                    plyr.stop();
-                                    plyr.play(&track.path);
-
-                                    app.current_track = Some(track.clone());
-                                    app.playback_duration = track.duration.unwrap_or(0);
-                                    app.playback_start = Some(Instant::now());
-                                }
+                                app.play_path(&track.path);
                             }
                         }
                     }
 
                     KeyCode::Char('p') => {
-                        app.autoplay_enabled = !app.autoplay_enabled;
+                        app.toggle_autoplay();
                     }
 
                     KeyCode::Char('c') => {
@@ -177,11 +196,45 @@ fn main() -> Result<()> {
                         app.play_next_track();
                     }
 
+                    KeyCode::Char('r') => app.cycle_resample_quality(),
+                    KeyCode::Char('x') => app.cycle_crossfade(),
+                    KeyCode::Char('X') => app.cycle_crossfade_curve(),
+                    KeyCode::Char('A') => app.toggle_album_aware_crossfade(),
+                    KeyCode::Char('g') => app.cycle_replaygain_mode(),
+                    KeyCode::Char('L') => app.start_loudness_scan(),
+
+                    KeyCode::Char('+') | KeyCode::Char('=') => app.change_volume(5),
+                    KeyCode::Char('-') => app.change_volume(-5),
+                    KeyCode::Char('m') => app.toggle_mute(),
+                    KeyCode::Char('(') => app.change_balance(-0.1),
+                    KeyCode::Char(')') => app.change_balance(0.1),
+                    KeyCode::Char('M') => app.toggle_mono(),
+                    KeyCode::Char('S') => app.toggle_swap_channels(),
+                    KeyCode::Char('<') => app.change_speed(-0.1),
+                    KeyCode::Char('>') => app.change_speed(0.1),
+                    KeyCode::Char('l') => app.cycle_loop(),
+
+                    KeyCode::Left => match app.screen {
+                        AppScreen::Equalizer => app.equalizer.move_left(),
+                        _ => app.seek_by(-5),
+                    },
+                    KeyCode::Right => match app.screen {
+                        AppScreen::Equalizer => app.equalizer.move_right(),
+                        _ => app.seek_by(5),
+                    },
+                    KeyCode::Char('[') => app.seek_by(-30),
+                    KeyCode::Char(']') => app.seek_by(30),
+
                     KeyCode::Backspace => {
                         if app.screen == AppScreen::Browser {
                             app.browser.go_up();
                         }
                     }
+                    KeyCode::Char(' ') if app.screen == AppScreen::Dsp => {
+                        app.toggle_dsp_stage();
+                    }
+                    KeyCode::Char('K') if app.screen == AppScreen::Dsp => app.move_dsp_stage(-1),
+                    KeyCode::Char('J') if app.screen == AppScreen::Dsp => app.move_dsp_stage(1),
                     KeyCode::Char(' ') => {
                         let mut lib = app.library_mut();
 
@@ -189,9 +242,25 @@ fn main() -> Result<()> {
                             lib.toggle_expanded();
                         }
                     }
+                    KeyCode::Char('s') if app.screen == AppScreen::Equalizer => {
+                        app.save_eq_preset();
+                    }
+                    KeyCode::Char('d') if app.screen == AppScreen::Equalizer => {
+                        app.delete_eq_preset();
+                    }
+                    KeyCode::Char('s') if app.screen == AppScreen::Visualizer => {
+                        app.toggle_visualizer_scale();
+                    }
+                    KeyCode::Char('h') if app.screen == AppScreen::Visualizer => {
+                        app.toggle_peak_hold();
+                    }
                     KeyCode::Tab => {
-                        let mut lib = app.library_mut();
-                        lib.tab_focus();
+                        if app.screen == AppScreen::Equalizer {
+                            app.equalizer.tab_focus();
+                        } else {
+                            let mut lib = app.library_mut();
+                            lib.tab_focus();
+                        }
                     }
                     _ => {}
                 }
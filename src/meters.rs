@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+
+use crate::player::LevelMeter;
+
+/// Level at the left end of the meters
+pub const FLOOR_DB: f32 = -48.0;
+
+/// How long a peak mark stays put, and how fast it falls afterwards
+const PEAK_HOLD_SECS: f32 = 1.5;
+const PEAK_FALL_DB_PER_SEC: f32 = 20.0;
+
+/// How long the clip indicator stays lit after the last clipped sample
+const CLIP_HOLD: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy)]
+pub struct MeterChannel {
+    pub rms_db: f32,
+
+    /// Recent peak, held for a moment before it falls
+    pub peak_db: f32,
+    held: f32,
+
+    clipped_at: Option<Instant>,
+}
+
+impl Default for MeterChannel {
+    fn default() -> Self {
+        Self {
+            rms_db: FLOOR_DB,
+            peak_db: FLOOR_DB,
+            held: 0.0,
+            clipped_at: None,
+        }
+    }
+}
+
+impl MeterChannel {
+    pub fn is_clipping(&self) -> bool {
+        self.clipped_at.is_some_and(|at| at.elapsed() < CLIP_HOLD)
+    }
+}
+
+/// Left and right output levels as shown in the footer
+pub struct MeterState {
+    pub channels: [MeterChannel; 2],
+    updated: Instant,
+}
+
+impl MeterState {
+    pub fn new() -> Self {
+        Self {
+            channels: [MeterChannel::default(); 2],
+            updated: Instant::now(),
+        }
+    }
+
+    /// Read what the player measured since the last call
+    pub fn update(&mut self, meter: &LevelMeter) {
+        let now = Instant::now();
+        let dt = now.duration_since(self.updated).as_secs_f32();
+        self.updated = now;
+
+        for (channel, level) in self.channels.iter_mut().zip(meter.take()) {
+            channel.rms_db = to_db(level.rms);
+
+            let peak_db = to_db(level.peak);
+            if peak_db >= channel.peak_db {
+                channel.peak_db = peak_db;
+                channel.held = 0.0;
+            } else {
+                channel.held += dt;
+                if channel.held > PEAK_HOLD_SECS {
+                    channel.peak_db = (channel.peak_db - PEAK_FALL_DB_PER_SEC * dt).max(peak_db);
+                }
+            }
+
+            if level.clipped {
+                channel.clipped_at = Some(now);
+            }
+        }
+    }
+}
+
+/// Level in dBFS, no lower than the meter floor
+fn to_db(level: f32) -> f32 {
+    (20.0 * level.max(1e-6).log10()).max(FLOOR_DB)
+}
@@ -1,7 +1,11 @@
 use crate::library::ArtistNode;
+use crate::player::EqPreset;
+use crate::settings::Settings;
 use std::{fs, path::Path};
 
 const SAVE_PATH: &str = "library.json"; // or "library.ron"
+const SETTINGS_PATH: &str = "settings.json";
+const EQ_PRESETS_PATH: &str = "eq_presets.json";
 
 pub fn save_library(artists: &[ArtistNode]) -> std::io::Result<()> {
     let data = serde_json::to_string_pretty(artists)?;
@@ -18,3 +22,35 @@ pub fn load_library() -> std::io::Result<Vec<ArtistNode>> {
         Ok(vec![]) // start empty if no file
     }
 }
+
+pub fn save_settings(settings: &Settings) -> std::io::Result<()> {
+    let data = serde_json::to_string_pretty(settings)?;
+    fs::write(SETTINGS_PATH, data)?;
+    Ok(())
+}
+
+pub fn load_settings() -> std::io::Result<Settings> {
+    if Path::new(SETTINGS_PATH).exists() {
+        let data = fs::read_to_string(SETTINGS_PATH)?;
+        let settings = serde_json::from_str(&data)?;
+        Ok(settings)
+    } else {
+        Ok(Settings::default())
+    }
+}
+
+pub fn save_eq_presets(presets: &[EqPreset]) -> std::io::Result<()> {
+    let data = serde_json::to_string_pretty(presets)?;
+    fs::write(EQ_PRESETS_PATH, data)?;
+    Ok(())
+}
+
+pub fn load_eq_presets() -> std::io::Result<Vec<EqPreset>> {
+    if Path::new(EQ_PRESETS_PATH).exists() {
+        let data = fs::read_to_string(EQ_PRESETS_PATH)?;
+        let presets = serde_json::from_str(&data)?;
+        Ok(presets)
+    } else {
+        Ok(vec![])
+    }
+}
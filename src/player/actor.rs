@@ -0,0 +1,236 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+use super::{
+    DeviceSelection, EventSender, FadeCurve, LevelMeter, OutputTap, Player, PlayerEvent,
+    PlayerEvents, ResampleQuality, StageKind, StageSlot,
+};
+
+/// Something for the player thread to do. Commands are carried out in the
+/// order they were sent; whatever fails is reported as a `PlayerEvent`.
+#[derive(Debug)]
+pub enum PlayerCommand {
+    /// Start `path` from the beginning, scaled by the linear ReplayGain
+    /// factor `gain`
+    Play {
+        path: PathBuf,
+        gain: f32,
+    },
+
+    /// Pause (`true`) or resume (`false`)
+    Pause(bool),
+
+    /// Pause if playing, resume if paused, as the player sees it when the
+    /// command arrives
+    TogglePause,
+
+    /// Jump to this many seconds into the current track
+    Seek(f64),
+
+    /// Loop between A and B seconds into the current track, `None` to stop
+    SetLoop(Option<(f64, f64)>),
+
+    /// Volume in percent, 0-100
+    SetVolume(u8),
+    SetMuted(bool),
+
+    /// -1.0 only left to 1.0 only right
+    SetBalance(f32),
+    SetMono(bool),
+    SetSwapChannels(bool),
+
+    /// Equalizer band gains in dB, lowest band first
+    SetEqGains([f32; 10]),
+
+    /// Order and on/off state of the DSP stages
+    SetDspChain(Vec<StageSlot>),
+
+    /// Track to continue with once the current one ends, with its gain,
+    /// or `None` to stop after it
+    Enqueue {
+        next: Option<(PathBuf, f32)>,
+        allow_crossfade: bool,
+    },
+
+    Stop,
+
+    /// Switch to another output device, `None` for the system default.
+    /// Playback stops.
+    SetDevice(Option<DeviceSelection>),
+
+    /// Stop and let go of the output so it is opened again on the next play
+    CloseOutput,
+
+    /// Tempo from 0.5 to 3.0, keeping the pitch
+    SetSpeed(f32),
+
+    /// Takes effect from the next track
+    SetResampleQuality(ResampleQuality),
+    SetCrossfade {
+        secs: f32,
+        curve: FadeCurve,
+    },
+}
+
+impl PlayerCommand {
+    /// Whether events sent before this command are about playback it ends
+    fn ends_session(&self) -> bool {
+        matches!(
+            self,
+            PlayerCommand::Play { .. }
+                | PlayerCommand::Stop
+                | PlayerCommand::SetDevice(_)
+                | PlayerCommand::CloseOutput
+        )
+    }
+}
+
+/// Snapshot of the player, republished after every command and a few times
+/// a second while playing
+#[derive(Debug, Clone, Default)]
+pub struct PlayerState {
+    /// Track reaching the device, `None` when stopped
+    pub track: Option<PathBuf>,
+    pub paused: bool,
+    pub position: Duration,
+
+    /// Times playback ran out of decoded audio, since startup
+    pub underruns: u64,
+
+    /// Delay each DSP stage adds, for the stages of the current stream
+    pub dsp_latencies: Vec<(StageKind, Duration)>,
+}
+
+/// How often the snapshot is refreshed when no commands arrive
+const SNAPSHOT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Drives a `Player` running on its own thread. Cheap to clone, so the UI,
+/// an IPC server or a test can all hold one and send commands concurrently;
+/// a slow file probe then only holds up the player thread.
+#[derive(Clone)]
+pub struct PlayerHandle {
+    commands: Sender<(u64, PlayerCommand)>,
+
+    /// Number of the latest session, bumped by commands that end one
+    session: Arc<AtomicU64>,
+    state: Arc<Mutex<PlayerState>>,
+
+    /// Read-only views of the audio leaving the stream callback, shared
+    /// with it so they can be read at the display's frame rate
+    pub tap: OutputTap,
+    pub meter: LevelMeter,
+}
+
+impl PlayerHandle {
+    /// Start the player thread, building the player there with `build` since
+    /// the audio output cannot move between threads. The thread exits once
+    /// every handle has been dropped.
+    pub fn spawn(build: impl FnOnce() -> Player + Send + 'static) -> (Self, PlayerEvents) {
+        let (commands, command_rx) = mpsc::channel::<(u64, PlayerCommand)>();
+        let (events_tx, events_rx) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let state = Arc::new(Mutex::new(PlayerState::default()));
+        let thread_state = Arc::clone(&state);
+
+        thread::spawn(move || {
+            let mut player = build();
+            player.events_tx = events_tx;
+
+            *thread_state.lock().unwrap() = player.snapshot();
+            let _ = ready_tx.send((player.session, player.tap.clone(), player.meter.clone()));
+
+            loop {
+                match command_rx.recv_timeout(SNAPSHOT_INTERVAL) {
+                    Ok((session, command)) => run(&mut player, session, command),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                *thread_state.lock().unwrap() = player.snapshot();
+            }
+
+            player.stop();
+        });
+
+        let (session, tap, meter) = ready_rx.recv().expect("player thread failed to start");
+        let session = Arc::new(AtomicU64::new(session));
+
+        let handle = Self {
+            commands,
+            session: Arc::clone(&session),
+            state,
+            tap,
+            meter,
+        };
+
+        (handle, PlayerEvents::new(events_rx, session))
+    }
+
+    pub fn send(&self, command: PlayerCommand) {
+        let session = if command.ends_session() {
+            self.session.fetch_add(1, Ordering::SeqCst) + 1
+        } else {
+            self.session.load(Ordering::SeqCst)
+        };
+
+        if self.commands.send((session, command)).is_err() {
+            log::error!("Player thread is gone");
+        }
+    }
+
+    /// The latest published snapshot
+    pub fn state(&self) -> PlayerState {
+        self.state.lock().unwrap().clone()
+    }
+}
+
+fn run(player: &mut Player, session: u64, command: PlayerCommand) {
+    if command.ends_session() {
+        player.stop();
+
+        // Handles sending at the same time may arrive out of order
+        player.session = player.session.max(session);
+    }
+
+    match command {
+        PlayerCommand::Play { path, gain } => {
+            if let Err(err) = player.play(&path, gain) {
+                let events = EventSender::new(player.events_tx.clone(), player.session);
+                events.send(PlayerEvent::Error(err));
+            }
+        }
+        PlayerCommand::Pause(paused) => player.set_paused(paused),
+        PlayerCommand::TogglePause => player.set_paused(!player.is_paused),
+        PlayerCommand::Seek(secs) => player.seek(secs),
+        PlayerCommand::SetLoop(region) => player.set_loop(region),
+        PlayerCommand::SetVolume(percent) => player.volume.set_percent(percent),
+        PlayerCommand::SetMuted(muted) => player.volume.set_muted(muted),
+        PlayerCommand::SetBalance(balance) => player.stereo.set_balance(balance),
+        PlayerCommand::SetMono(mono) => player.stereo.set_mono(mono),
+        PlayerCommand::SetSwapChannels(swap) => player.stereo.set_swapped(swap),
+        PlayerCommand::SetEqGains(gains) => player.eq.set_gains(&gains),
+        PlayerCommand::SetDspChain(slots) => player.dsp.set_slots(&slots),
+        PlayerCommand::Enqueue {
+            next,
+            allow_crossfade,
+        } => player.set_next(next, allow_crossfade),
+        PlayerCommand::Stop => {}
+        PlayerCommand::SetDevice(device) => player.set_device(device),
+        PlayerCommand::CloseOutput => player.close_output(),
+        PlayerCommand::SetSpeed(speed) => player.set_speed(speed),
+        PlayerCommand::SetResampleQuality(quality) => player.resample_quality = quality,
+        PlayerCommand::SetCrossfade { secs, curve } => {
+            player.crossfade_secs = secs;
+            player.crossfade_curve = curve;
+        }
+    }
+}
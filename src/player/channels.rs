@@ -0,0 +1,217 @@
+use symphonia::core::audio::Channels;
+
+/// -3 dB, the usual weight for centre and surround channels in a stereo fold-down
+const MINUS_3DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Maps interleaved frames from the file's channel layout to the device's
+/// channel count.
+///
+/// Everything that does not match the device is folded to stereo first
+/// (ITU-R BS.775 style coefficients, LFE dropped) and then spread over the
+/// device channels: mono devices get the average, wider devices get left and
+/// right on their first two channels and silence elsewhere.
+pub struct ChannelMapper {
+    input_channels: usize,
+
+    /// Row-major `output_channels x input_channels` mixing matrix, `None` when
+    /// the layouts already match
+    matrix: Option<Vec<f32>>,
+}
+
+impl ChannelMapper {
+    pub fn new(layout: Channels, output_channels: usize) -> Self {
+        let input_channels = layout.count().max(1);
+        let output_channels = output_channels.max(1);
+
+        let matrix = if input_channels == output_channels {
+            None
+        } else {
+            Some(build_matrix(layout, input_channels, output_channels))
+        };
+
+        Self {
+            input_channels,
+            matrix,
+        }
+    }
+
+    pub fn input_channels(&self) -> usize {
+        self.input_channels
+    }
+
+    pub fn process(&self, input: &[f32], output: &mut Vec<f32>) {
+        let Some(matrix) = &self.matrix else {
+            output.extend_from_slice(input);
+            return;
+        };
+
+        for frame in input.chunks_exact(self.input_channels) {
+            for row in matrix.chunks_exact(self.input_channels) {
+                let mixed = row.iter().zip(frame).map(|(gain, s)| gain * s).sum();
+                output.push(mixed);
+            }
+        }
+    }
+}
+
+fn build_matrix(layout: Channels, input_channels: usize, output_channels: usize) -> Vec<f32> {
+    // Fold to stereo: one (left, right) weight pair per input channel
+    let stereo: Vec<(f32, f32)> = if input_channels == 1 {
+        vec![(1.0, 1.0)]
+    } else if layout.is_empty() {
+        // Unknown layout, treat the first two channels as left/right
+        (0..input_channels)
+            .map(|ch| match ch {
+                0 => (1.0, 0.0),
+                1 => (0.0, 1.0),
+                _ => (0.0, 0.0),
+            })
+            .collect()
+    } else {
+        layout.iter().map(stereo_weights).collect()
+    };
+
+    // Keep a full-scale signal on every input from clipping after the fold-down
+    let norm_left: f32 = stereo.iter().map(|(l, _)| l).sum();
+    let norm_right: f32 = stereo.iter().map(|(_, r)| r).sum();
+    let norm = if input_channels == 1 {
+        1.0
+    } else {
+        norm_left.max(norm_right).max(1.0)
+    };
+
+    let left: Vec<f32> = stereo.iter().map(|(l, _)| l / norm).collect();
+    let right: Vec<f32> = stereo.iter().map(|(_, r)| r / norm).collect();
+
+    let mut matrix = Vec::with_capacity(output_channels * input_channels);
+
+    if output_channels == 1 {
+        matrix.extend(left.iter().zip(&right).map(|(l, r)| 0.5 * (l + r)));
+    } else {
+        matrix.extend(&left);
+        matrix.extend(&right);
+        matrix.resize(output_channels * input_channels, 0.0);
+    }
+
+    matrix
+}
+
+fn stereo_weights(position: Channels) -> (f32, f32) {
+    match position {
+        Channels::FRONT_LEFT
+        | Channels::FRONT_LEFT_CENTRE
+        | Channels::FRONT_LEFT_WIDE
+        | Channels::FRONT_LEFT_HIGH => (1.0, 0.0),
+        Channels::FRONT_RIGHT
+        | Channels::FRONT_RIGHT_CENTRE
+        | Channels::FRONT_RIGHT_WIDE
+        | Channels::FRONT_RIGHT_HIGH => (0.0, 1.0),
+        Channels::FRONT_CENTRE | Channels::FRONT_CENTRE_HIGH | Channels::TOP_CENTRE => {
+            (MINUS_3DB, MINUS_3DB)
+        }
+        Channels::REAR_LEFT
+        | Channels::SIDE_LEFT
+        | Channels::REAR_LEFT_CENTRE
+        | Channels::TOP_FRONT_LEFT
+        | Channels::TOP_REAR_LEFT => (MINUS_3DB, 0.0),
+        Channels::REAR_RIGHT
+        | Channels::SIDE_RIGHT
+        | Channels::REAR_RIGHT_CENTRE
+        | Channels::TOP_FRONT_RIGHT
+        | Channels::TOP_REAR_RIGHT => (0.0, MINUS_3DB),
+        Channels::REAR_CENTRE | Channels::TOP_FRONT_CENTRE | Channels::TOP_REAR_CENTRE => {
+            (0.5, 0.5)
+        }
+        // LFE is left out of stereo fold-downs
+        Channels::LFE1 | Channels::LFE2 => (0.0, 0.0),
+        _ => (0.5, 0.5),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(layout: Channels, output_channels: usize, input: &[f32]) -> Vec<f32> {
+        let mut output = Vec::new();
+        ChannelMapper::new(layout, output_channels).process(input, &mut output);
+        output
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len(), "{actual:?} vs {expected:?}");
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{actual:?} vs {expected:?}");
+        }
+    }
+
+    const STEREO: Channels = Channels::FRONT_LEFT.union(Channels::FRONT_RIGHT);
+
+    #[test]
+    fn matching_layouts_pass_through() {
+        let input = [0.1, -0.2, 0.3, -0.4];
+        assert_eq!(map(STEREO, 2, &input), input);
+    }
+
+    #[test]
+    fn mono_goes_to_both_sides() {
+        assert_close(
+            &map(Channels::FRONT_LEFT, 2, &[0.5, -0.25]),
+            &[0.5, 0.5, -0.25, -0.25],
+        );
+    }
+
+    #[test]
+    fn stereo_to_mono_averages() {
+        assert_close(&map(STEREO, 1, &[1.0, 0.0, 0.2, 0.4]), &[0.5, 0.3]);
+    }
+
+    #[test]
+    fn stereo_fills_the_front_of_wider_devices() {
+        assert_close(&map(STEREO, 4, &[0.1, 0.2]), &[0.1, 0.2, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn surround_folds_down_without_clipping() {
+        // 5.1 in the usual order: FL FR FC LFE RL RR
+        let layout = Channels::FRONT_LEFT
+            | Channels::FRONT_RIGHT
+            | Channels::FRONT_CENTRE
+            | Channels::LFE1
+            | Channels::REAR_LEFT
+            | Channels::REAR_RIGHT;
+        let norm = 1.0 + 2.0 * MINUS_3DB;
+
+        // Every channel at full scale stays at full scale
+        assert_close(&map(layout, 2, &[1.0; 6]), &[1.0, 1.0]);
+
+        // Centre lands in the middle at -3 dB, the LFE nowhere
+        let centre = MINUS_3DB / norm;
+        assert_close(
+            &map(layout, 2, &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0]),
+            &[centre, centre],
+        );
+        assert_close(
+            &map(layout, 2, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0]),
+            &[0.0, 0.0],
+        );
+
+        // Rear left only reaches the left side
+        assert_close(
+            &map(layout, 2, &[0.0, 0.0, 0.0, 0.0, 1.0, 0.0]),
+            &[MINUS_3DB / norm, 0.0],
+        );
+    }
+
+    #[test]
+    fn unknown_layout_keeps_the_first_two_channels() {
+        let mut output = Vec::new();
+        let mapper = ChannelMapper {
+            input_channels: 3,
+            matrix: Some(build_matrix(Channels::empty(), 3, 2)),
+        };
+        mapper.process(&[0.1, 0.2, 0.9], &mut output);
+
+        assert_close(&output, &[0.1, 0.2]);
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Playback position of the audible track, advanced by the stream callback
+/// as it hands samples to the device.
+///
+/// The callback rebases the clock whenever the sample stream jumps: to zero
+/// when a queued track starts, and to the seek target after a seek. The
+/// position is then the base time plus whatever has been played since,
+/// scaled by the playback speed.
+pub struct PlaybackClock {
+    samples_per_sec: f64,
+
+    /// Ring read position the base time refers to, and the current one
+    base_at: AtomicU64,
+    read: AtomicU64,
+
+    /// Track time at `base_at`, in seconds as f64 bits
+    base_secs: AtomicU64,
+
+    /// Seconds of track per second of output, f64 bits
+    speed: AtomicU64,
+}
+
+impl PlaybackClock {
+    pub fn new(sample_rate: u32, channels: usize, speed: f32) -> Self {
+        Self {
+            samples_per_sec: (sample_rate as usize * channels.max(1)).max(1) as f64,
+            base_at: AtomicU64::new(0),
+            read: AtomicU64::new(0),
+            base_secs: AtomicU64::new(0f64.to_bits()),
+            speed: AtomicU64::new((speed as f64).to_bits()),
+        }
+    }
+
+    /// The sample at ring position `at` is `secs` into the track
+    pub fn rebase(&self, at: u64, secs: f64) {
+        self.base_at.store(at, Ordering::Relaxed);
+        self.base_secs.store(secs.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Samples from the last rebase on were stretched to `speed`
+    pub fn set_speed(&self, speed: f32) {
+        self.speed.store((speed as f64).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Everything before ring position `read` has reached the device
+    pub fn advance_to(&self, read: u64) {
+        self.read.store(read, Ordering::Release);
+    }
+
+    pub fn position(&self) -> Duration {
+        let read = self.read.load(Ordering::Acquire);
+        let base_at = self.base_at.load(Ordering::Relaxed);
+        let base_secs = f64::from_bits(self.base_secs.load(Ordering::Relaxed));
+
+        let speed = f64::from_bits(self.speed.load(Ordering::Relaxed));
+
+        let played = read.saturating_sub(base_at) as f64 / self.samples_per_sec * speed;
+        Duration::from_secs_f64((base_secs + played).max(0.0))
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use super::dsp::{DspChainControl, StageKind};
+use super::source::Source;
+
+/// Longest crossfade the player accepts, in seconds
+pub const MAX_CROSSFADE_SECS: f32 = 12.0;
+
+/// Gain curve applied to the outgoing and incoming track during a crossfade
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    /// Constant-power sine/cosine fade, avoids the dip in the middle of a linear fade
+    EqualPower,
+}
+
+impl FadeCurve {
+    pub fn next(self) -> Self {
+        match self {
+            FadeCurve::Linear => FadeCurve::EqualPower,
+            FadeCurve::EqualPower => FadeCurve::Linear,
+        }
+    }
+
+    /// Gains for the outgoing and incoming track at progress `t` (0.0..=1.0)
+    pub fn gains(self, t: f32) -> (f32, f32) {
+        match self {
+            FadeCurve::Linear => (1.0 - t, t),
+            FadeCurve::EqualPower => {
+                let angle = t * std::f32::consts::FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            }
+        }
+    }
+}
+
+/// How the decode thread should hand over to a queued track
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrossfadeSpec {
+    pub secs: f32,
+    pub curve: FadeCurve,
+}
+
+/// Mixes the tail of the outgoing track under the start of the incoming one.
+///
+/// The decode thread keeps decoding the incoming track as usual and passes
+/// each chunk through `mix`, which decodes as much of the outgoing track as
+/// it needs to overlay.
+///
+/// The DSP chain applies the incoming track's ReplayGain to the mix, so
+/// while its ReplayGain stage is on the outgoing track is pre-scaled by the
+/// ratio of the two gains.
+pub struct Crossfade {
+    outgoing: Source,
+    outgoing_scale: f32,
+    dsp: DspChainControl,
+    outgoing_buf: VecDeque<f32>,
+    outgoing_finished: bool,
+    curve: FadeCurve,
+    channels: usize,
+
+    /// Length of the fade and progress through it, in frames
+    fade_frames: usize,
+    done_frames: usize,
+}
+
+impl Crossfade {
+    pub fn new(
+        outgoing: Source,
+        incoming_gain: f32,
+        dsp: DspChainControl,
+        spec: CrossfadeSpec,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Self {
+        let fade_frames = (spec.secs.clamp(0.0, MAX_CROSSFADE_SECS) * sample_rate as f32) as usize;
+
+        Self {
+            outgoing_scale: outgoing.gain / incoming_gain.max(f32::EPSILON),
+            outgoing,
+            dsp,
+            outgoing_buf: VecDeque::new(),
+            outgoing_finished: false,
+            curve: spec.curve,
+            channels: channels.max(1),
+            fade_frames: fade_frames.max(1),
+            done_frames: 0,
+        }
+    }
+
+    /// Overlay the outgoing track onto `incoming`, in place
+    pub fn mix(&mut self, incoming: &mut [f32]) {
+        while self.outgoing_buf.len() < incoming.len() && !self.outgoing_finished {
+            let mut chunk = Vec::new();
+            self.outgoing_finished = !self.outgoing.decode_next(&mut chunk);
+            self.outgoing_buf.extend(chunk);
+        }
+
+        let scale = if self.dsp.is_enabled(StageKind::ReplayGain) {
+            self.outgoing_scale
+        } else {
+            1.0
+        };
+
+        for frame in incoming.chunks_mut(self.channels) {
+            let t = (self.done_frames as f32 / self.fade_frames as f32).min(1.0);
+            let (gain_out, gain_in) = self.curve.gains(t);
+            let gain_out = gain_out * scale;
+
+            for sample in frame.iter_mut() {
+                let outgoing = if t < 1.0 {
+                    self.outgoing_buf.pop_front().unwrap_or(0.0)
+                } else {
+                    0.0
+                };
+
+                *sample = *sample * gain_in + outgoing * gain_out;
+            }
+
+            self.done_frames += 1;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.done_frames >= self.fade_frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::player::output::{SinkFormat, write_wav};
+    use crate::player::source::OutputFormat;
+    use crate::player::{ResampleQuality, StageSlot, default_slots};
+
+    const RATE: u32 = 8000;
+
+    /// A stereo track holding `level` throughout, opened with `gain`
+    fn outgoing(name: &str, level: f32, gain: f32) -> Source {
+        let dir = std::env::temp_dir().join(format!("shelltrax-crossfade-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path: PathBuf = dir.join(name);
+
+        let format = SinkFormat {
+            sample_rate: RATE,
+            channels: 2,
+        };
+        write_wav(&path, format, &vec![level; RATE as usize * 2]);
+
+        let output = OutputFormat {
+            sample_rate: RATE,
+            channels: 2,
+            resample_quality: ResampleQuality::default(),
+        };
+        Source::open(&path, output, gain).unwrap()
+    }
+
+    fn spec(secs: f32) -> CrossfadeSpec {
+        CrossfadeSpec {
+            secs,
+            curve: FadeCurve::Linear,
+        }
+    }
+
+    #[test]
+    fn linear_gains_sum_to_one() {
+        for i in 0..=100 {
+            let (out, inc) = FadeCurve::Linear.gains(i as f32 / 100.0);
+            assert!((out + inc - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn equal_power_keeps_power_constant() {
+        for i in 0..=100 {
+            let (out, inc) = FadeCurve::EqualPower.gains(i as f32 / 100.0);
+            assert!((out * out + inc * inc - 1.0).abs() < 1e-6);
+        }
+
+        assert_eq!(FadeCurve::EqualPower.gains(0.0), (1.0, 0.0));
+    }
+
+    #[test]
+    fn mix_finishes_after_the_fade_length() {
+        let source = outgoing("length.wav", 0.5, 1.0);
+        let dsp = DspChainControl::default();
+        let mut fade = Crossfade::new(source, 1.0, dsp, spec(0.1), RATE, 2);
+
+        // 800 frames of fade, fed 100 at a time
+        for _ in 0..7 {
+            fade.mix(&mut [1.0; 200]);
+            assert!(!fade.is_finished());
+        }
+        fade.mix(&mut [1.0; 200]);
+        assert!(fade.is_finished());
+
+        // Past the end the incoming track passes unchanged
+        let mut after = [1.0; 200];
+        fade.mix(&mut after);
+        assert!(after.iter().all(|&x| x == 1.0));
+    }
+
+    #[test]
+    fn outgoing_scale_follows_the_replaygain_stage() {
+        // The outgoing track plays at half the incoming track's gain
+        let first_sample = |enabled: bool| {
+            let dsp = DspChainControl::default();
+            let slots: Vec<_> = default_slots()
+                .into_iter()
+                .map(|slot| StageSlot {
+                    enabled: slot.enabled && (enabled || slot.kind != StageKind::ReplayGain),
+                    ..slot
+                })
+                .collect();
+            dsp.set_slots(&slots);
+
+            let source = outgoing(&format!("scale-{enabled}.wav"), 0.5, 0.5);
+            let mut fade = Crossfade::new(source, 1.0, dsp, spec(1.0), RATE, 2);
+
+            let mut mix = [0.0; 2];
+            fade.mix(&mut mix);
+            mix[0]
+        };
+
+        assert!((first_sample(true) - 0.25).abs() < 1e-3);
+        assert!((first_sample(false) - 0.5).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,57 @@
+use super::dsp::{DspStage, StageKind};
+
+/// Cutoff of the low-pass applied to the signal bled into the other ear, in Hz
+const CUTOFF_HZ: f32 = 700.0;
+
+/// Level of the bled signal relative to the direct one, about -6 dB
+const FEED: f32 = 0.5;
+
+/// Headphone crossfeed: mixes a low-passed copy of each stereo channel into
+/// the other one, softening hard-panned recordings the way speakers would.
+/// Only the first two channels are touched; mono output is left alone.
+pub struct Crossfeed {
+    channels: usize,
+
+    /// One-pole low-pass coefficient and state for left and right
+    coeff: f32,
+    low: [f32; 2],
+}
+
+impl Crossfeed {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            channels: channels.max(1),
+            coeff: 1.0 - (-2.0 * std::f32::consts::PI * CUTOFF_HZ / sample_rate as f32).exp(),
+            low: [0.0; 2],
+        }
+    }
+}
+
+impl DspStage for Crossfeed {
+    fn kind(&self) -> StageKind {
+        StageKind::Crossfeed
+    }
+
+    fn process(&mut self, frames: &mut [f32]) {
+        if self.channels < 2 {
+            return;
+        }
+
+        // Keep the summed level about where it was
+        let norm = 1.0 / (1.0 + FEED);
+
+        for frame in frames.chunks_mut(self.channels) {
+            let (left, right) = (frame[0], frame[1]);
+
+            self.low[0] += (left - self.low[0]) * self.coeff;
+            self.low[1] += (right - self.low[1]) * self.coeff;
+
+            frame[0] = (left + self.low[1] * FEED) * norm;
+            frame[1] = (right + self.low[0] * FEED) * norm;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.low = [0.0; 2];
+    }
+}
@@ -1,5 +1,6 @@
 use std::fs::File;
 use std::path::Path;
+use symphonia::core::audio::{AudioBufferRef, Signal};
 use symphonia::core::codecs::{CODEC_TYPE_NULL, Decoder, DecoderOptions};
 use symphonia::core::formats::{FormatOptions, FormatReader, Track};
 use symphonia::core::io::MediaSourceStream;
@@ -33,3 +34,70 @@ pub fn open_decoder(
 
     Ok((format, decoder, track.clone()))
 }
+
+/// Append a decoded buffer to `samples` as interleaved f32 frames.
+/// Returns `false` if the buffer's sample format is not supported.
+pub fn interleave(decoded: AudioBufferRef, samples: &mut Vec<f32>) -> bool {
+    match &decoded {
+        AudioBufferRef::F32(_) => log::debug!("Decoded buffer format: F32"),
+        AudioBufferRef::S16(_) => log::debug!("Decoded buffer format: S16"),
+        AudioBufferRef::U8(_) => log::debug!("Decoded buffer format: U8"),
+        AudioBufferRef::S24(_) => log::debug!("Decoded buffer format: S24"),
+        AudioBufferRef::F64(_) => log::debug!("Decoded buffer format: F64"),
+        AudioBufferRef::S32(_) => log::debug!("Decoded buffer format: S32"),
+        _ => log::debug!("Decoded buffer format: Unknown/Unsupported"),
+    }
+
+    match decoded {
+        AudioBufferRef::F32(buf) => {
+            for frame in 0..buf.frames() {
+                for ch in 0..buf.spec().channels.count() {
+                    samples.push(buf.chan(ch)[frame]);
+                }
+            }
+        }
+        AudioBufferRef::S16(buf) => {
+            for frame in 0..buf.frames() {
+                for ch in 0..buf.spec().channels.count() {
+                    samples.push(buf.chan(ch)[frame] as f32 / i16::MAX as f32);
+                }
+            }
+        }
+        AudioBufferRef::U8(buf) => {
+            for frame in 0..buf.frames() {
+                for ch in 0..buf.spec().channels.count() {
+                    samples.push(buf.chan(ch)[frame] as f32 / u8::MAX as f32);
+                }
+            }
+        }
+        AudioBufferRef::S24(buf) => {
+            for frame in 0..buf.frames() {
+                for ch in 0..buf.spec().channels.count() {
+                    let val = buf.chan(ch)[frame];
+                    let sample_f32 = val.inner() as f32 / (1 << 23) as f32;
+                    samples.push(sample_f32);
+                }
+            }
+        }
+        AudioBufferRef::F64(buf) => {
+            for frame in 0..buf.frames() {
+                for ch in 0..buf.spec().channels.count() {
+                    samples.push(buf.chan(ch)[frame] as f32);
+                }
+            }
+        }
+        AudioBufferRef::S32(buf) => {
+            for frame in 0..buf.frames() {
+                for ch in 0..buf.spec().channels.count() {
+                    samples.push(buf.chan(ch)[frame] as f32 / i32::MAX as f32);
+                }
+            }
+        }
+        _ => {
+            log::debug!("Unsupported buffer format");
+            return false;
+        }
+    }
+
+    true
+}
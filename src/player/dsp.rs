@@ -0,0 +1,472 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// One processing step in the output path.
+///
+/// Stages run in the stream callback on interleaved f32 frames at the output
+/// rate and channel count, so `process` must not block or allocate.
+pub trait DspStage: Send {
+    fn kind(&self) -> StageKind;
+
+    /// Process interleaved frames in place
+    fn process(&mut self, frames: &mut [f32]);
+
+    /// Forget filter memory and envelopes. Called when the stream becomes
+    /// discontinuous, e.g. after a seek.
+    fn reset(&mut self) {}
+
+    /// Delay the stage adds to the signal, in frames
+    fn latency_frames(&self) -> usize {
+        0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageKind {
+    ReplayGain,
+    Equalizer,
+    Crossfeed,
+    Stereo,
+    Volume,
+    Limiter,
+}
+
+impl StageKind {
+    pub const ALL: [StageKind; 6] = [
+        StageKind::ReplayGain,
+        StageKind::Equalizer,
+        StageKind::Crossfeed,
+        StageKind::Stereo,
+        StageKind::Volume,
+        StageKind::Limiter,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StageKind::ReplayGain => "ReplayGain",
+            StageKind::Equalizer => "Equalizer",
+            StageKind::Crossfeed => "Crossfeed",
+            StageKind::Stereo => "Stereo",
+            StageKind::Volume => "Volume",
+            StageKind::Limiter => "Limiter",
+        }
+    }
+
+    fn enabled_by_default(self) -> bool {
+        !matches!(self, StageKind::Crossfeed | StageKind::Limiter)
+    }
+}
+
+/// Position and on/off state of a stage in the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageSlot {
+    pub kind: StageKind,
+    pub enabled: bool,
+}
+
+/// Drop duplicate stages and append missing ones in their default state
+pub fn normalize_slots(slots: &[StageSlot]) -> Vec<StageSlot> {
+    let mut normalized: Vec<StageSlot> = Vec::new();
+
+    for slot in slots.iter().copied().chain(default_slots()) {
+        if !normalized.iter().any(|s| s.kind == slot.kind) {
+            normalized.push(slot);
+        }
+    }
+
+    normalized
+}
+
+pub fn default_slots() -> Vec<StageSlot> {
+    StageKind::ALL
+        .iter()
+        .map(|&kind| StageSlot {
+            kind,
+            enabled: kind.enabled_by_default(),
+        })
+        .collect()
+}
+
+/// Chain order and on/off state, shared between the player and the stream
+/// callback. The layout is packed into one atomic, a byte per position:
+/// the stage's index in `StageKind::ALL` plus one, with the top bit set when
+/// it is enabled, so the callback reads it without locking.
+#[derive(Clone)]
+pub struct DspChainControl {
+    layout: Arc<AtomicU64>,
+
+    /// Latency of each stage, worked out by the player whenever it builds a
+    /// chain; the callback never touches it
+    latencies: Arc<Mutex<Vec<(StageKind, Duration)>>>,
+}
+
+/// Bit marking an enabled stage in a packed layout
+const ENABLED_BIT: u8 = 0x80;
+
+const _: () = assert!(StageKind::ALL.len() <= 8, "a chain layout holds at most 8 stages");
+
+fn pack(slots: &[StageSlot]) -> u64 {
+    slots.iter().enumerate().fold(0, |layout, (position, slot)| {
+        let index = StageKind::ALL.iter().position(|kind| *kind == slot.kind);
+        let mut byte = index.map_or(0, |index| index as u8 + 1);
+        if slot.enabled {
+            byte |= ENABLED_BIT;
+        }
+        layout | ((byte as u64) << (position * 8))
+    })
+}
+
+/// Position and on/off state of each stage in a packed layout, in order
+fn unpack(layout: u64) -> impl Iterator<Item = StageSlot> {
+    layout
+        .to_le_bytes()
+        .into_iter()
+        .map_while(|byte| {
+            let kind = StageKind::ALL.get(((byte & !ENABLED_BIT) as usize).checked_sub(1)?)?;
+            Some(StageSlot {
+                kind: *kind,
+                enabled: byte & ENABLED_BIT != 0,
+            })
+        })
+}
+
+impl Default for DspChainControl {
+    fn default() -> Self {
+        Self {
+            layout: Arc::new(AtomicU64::new(pack(&default_slots()))),
+            latencies: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl DspChainControl {
+    pub fn is_enabled(&self, kind: StageKind) -> bool {
+        unpack(self.layout.load(Ordering::Acquire)).any(|slot| slot.kind == kind && slot.enabled)
+    }
+
+    /// Replace the chain layout, normalised with `normalize_slots`
+    pub fn set_slots(&self, slots: &[StageSlot]) {
+        self.layout
+            .store(pack(&normalize_slots(slots)), Ordering::Release);
+    }
+
+    /// Latency of each stage of the most recently built chain
+    pub fn latencies(&self) -> Vec<(StageKind, Duration)> {
+        self.latencies.lock().unwrap().clone()
+    }
+}
+
+/// How long a stage takes to fade in or out when it is switched on or off
+const TOGGLE_FADE_SECS: f32 = 0.01;
+
+/// Frames of a stage's input kept at a time while it fades
+const DRY_FRAMES: usize = 1024;
+
+/// A stage in the chain and how much of it is heard
+struct ChainSlot {
+    stage: Box<dyn DspStage>,
+    enabled: bool,
+
+    /// 0.0 bypassed to 1.0 fully on; ramps towards `enabled` so switching
+    /// a stage never cuts between its input and output
+    mix: f32,
+}
+
+/// The stages owned by the stream callback, run in the order set by the
+/// shared `DspChainControl`
+pub struct DspChain {
+    control: DspChainControl,
+
+    /// Packed layout the stages are currently arranged by
+    layout: Option<u64>,
+
+    stages: Vec<ChainSlot>,
+    channels: usize,
+
+    /// Change of `mix` per frame while a stage fades
+    step: f32,
+
+    /// Input of a fading stage, to mix with its output
+    dry: Vec<f32>,
+}
+
+impl DspChain {
+    /// Build the chain; called before the stream starts, so it may lock and
+    /// allocate
+    pub fn new(
+        control: DspChainControl,
+        sample_rate: u32,
+        channels: usize,
+        stages: Vec<Box<dyn DspStage>>,
+    ) -> Self {
+        let rate = sample_rate.max(1) as f64;
+        *control.latencies.lock().unwrap() = stages
+            .iter()
+            .map(|stage| {
+                let secs = stage.latency_frames() as f64 / rate;
+                (stage.kind(), Duration::from_secs_f64(secs))
+            })
+            .collect();
+
+        let channels = channels.max(1);
+        let mut chain = Self {
+            control,
+            layout: None,
+            stages: stages
+                .into_iter()
+                .map(|stage| ChainSlot {
+                    stage,
+                    enabled: false,
+                    mix: 0.0,
+                })
+                .collect(),
+            channels,
+            step: 1.0 / (TOGGLE_FADE_SECS * rate as f32).max(1.0),
+            dry: vec![0.0; DRY_FRAMES * channels],
+        };
+
+        // Stages that start switched on are heard from the first frame
+        chain.refresh();
+        for slot in chain.stages.iter_mut() {
+            slot.mix = if slot.enabled { 1.0 } else { 0.0 };
+        }
+        chain
+    }
+
+    /// Pick up order and on/off changes with one atomic load
+    fn refresh(&mut self) {
+        let layout = self.control.layout.load(Ordering::Acquire);
+        if self.layout == Some(layout) {
+            return;
+        }
+
+        let slot = |kind: StageKind| unpack(layout).enumerate().find(|(_, s)| s.kind == kind);
+
+        self.stages
+            .sort_unstable_by_key(|s| slot(s.stage.kind()).map_or(usize::MAX, |(i, _)| i));
+
+        for chain_slot in self.stages.iter_mut() {
+            let now = slot(chain_slot.stage.kind()).is_some_and(|(_, s)| s.enabled);
+
+            // Start from a clean state when a bypassed stage is switched back
+            // on; one still fading out carries on from where it is
+            if now && !chain_slot.enabled && chain_slot.mix == 0.0 {
+                chain_slot.stage.reset();
+            }
+            chain_slot.enabled = now;
+        }
+
+        self.layout = Some(layout);
+    }
+
+    pub fn process(&mut self, frames: &mut [f32]) {
+        self.refresh();
+
+        let channels = self.channels;
+        let step = self.step;
+
+        for slot in self.stages.iter_mut() {
+            let target = if slot.enabled { 1.0 } else { 0.0 };
+            if slot.mix == target {
+                if slot.enabled {
+                    slot.stage.process(frames);
+                }
+                continue;
+            }
+
+            // Fading in or out: blend the stage's output with its input
+            for chunk in frames.chunks_mut(self.dry.len()) {
+                let dry = &mut self.dry[..chunk.len()];
+                dry.copy_from_slice(chunk);
+                slot.stage.process(chunk);
+
+                for (wet, dry) in chunk.chunks_mut(channels).zip(dry.chunks(channels)) {
+                    slot.mix = if slot.enabled {
+                        (slot.mix + step).min(1.0)
+                    } else {
+                        (slot.mix - step).max(0.0)
+                    };
+
+                    // Raised cosine, like the output fade
+                    let gain = 0.5 - 0.5 * (std::f32::consts::PI * slot.mix).cos();
+                    for (wet, dry) in wet.iter_mut().zip(dry) {
+                        *wet = dry + (*wet - dry) * gain;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for slot in self.stages.iter_mut() {
+            slot.stage.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    const RATE: u32 = 1000;
+
+    /// Applies `op` to every sample and counts its resets
+    struct TestStage {
+        kind: StageKind,
+        op: fn(f32) -> f32,
+        resets: Arc<AtomicUsize>,
+    }
+
+    impl DspStage for TestStage {
+        fn kind(&self) -> StageKind {
+            self.kind
+        }
+
+        fn process(&mut self, frames: &mut [f32]) {
+            frames.iter_mut().for_each(|x| *x = (self.op)(*x));
+        }
+
+        fn reset(&mut self) {
+            self.resets.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn slot(kind: StageKind, enabled: bool) -> StageSlot {
+        StageSlot { kind, enabled }
+    }
+
+    /// A mono chain of "add one" (ReplayGain) and "double" (Volume)
+    fn chain(slots: &[StageSlot]) -> (DspChain, DspChainControl, Arc<AtomicUsize>) {
+        let control = DspChainControl::default();
+        control.set_slots(slots);
+
+        let resets = Arc::new(AtomicUsize::new(0));
+        let stages: Vec<Box<dyn DspStage>> = vec![
+            Box::new(TestStage {
+                kind: StageKind::ReplayGain,
+                op: |x| x + 1.0,
+                resets: Arc::clone(&resets),
+            }),
+            Box::new(TestStage {
+                kind: StageKind::Volume,
+                op: |x| x * 2.0,
+                resets: Arc::new(AtomicUsize::new(0)),
+            }),
+        ];
+
+        (
+            DspChain::new(control.clone(), RATE, 1, stages),
+            control,
+            resets,
+        )
+    }
+
+    fn run(chain: &mut DspChain, frames: usize) -> Vec<f32> {
+        let mut data = vec![0.0; frames];
+        chain.process(&mut data);
+        data
+    }
+
+    #[test]
+    fn layout_survives_packing() {
+        let slots = vec![
+            slot(StageKind::Limiter, true),
+            slot(StageKind::ReplayGain, false),
+            slot(StageKind::Stereo, true),
+            slot(StageKind::Equalizer, false),
+            slot(StageKind::Volume, true),
+            slot(StageKind::Crossfeed, true),
+        ];
+
+        assert_eq!(unpack(pack(&slots)).collect::<Vec<_>>(), slots);
+        assert_eq!(
+            unpack(pack(&default_slots())).collect::<Vec<_>>(),
+            default_slots()
+        );
+    }
+
+    #[test]
+    fn slots_are_normalized() {
+        let control = DspChainControl::default();
+        control.set_slots(&[
+            slot(StageKind::Volume, false),
+            slot(StageKind::Volume, true),
+        ]);
+
+        let layout: Vec<_> = unpack(control.layout.load(Ordering::Acquire)).collect();
+        assert_eq!(layout.len(), StageKind::ALL.len());
+        assert_eq!(layout[0], slot(StageKind::Volume, false));
+        assert!(!control.is_enabled(StageKind::Volume));
+        assert!(control.is_enabled(StageKind::ReplayGain));
+    }
+
+    #[test]
+    fn stages_run_in_layout_order() {
+        let (mut chain, control, _) = chain(&[
+            slot(StageKind::ReplayGain, true),
+            slot(StageKind::Volume, true),
+        ]);
+        assert_eq!(run(&mut chain, 4), vec![2.0; 4]);
+
+        // Reordering takes effect straight away
+        control.set_slots(&[
+            slot(StageKind::Volume, true),
+            slot(StageKind::ReplayGain, true),
+        ]);
+        assert_eq!(run(&mut chain, 4), vec![1.0; 4]);
+    }
+
+    #[test]
+    fn switched_stages_fade_between_input_and_output() {
+        let (mut chain, control, resets) = chain(&[
+            slot(StageKind::ReplayGain, true),
+            slot(StageKind::Volume, false),
+        ]);
+        assert_eq!(run(&mut chain, 4), vec![1.0; 4]);
+
+        // Switched off: from its output (1.0) down to its input (0.0) over
+        // 10 ms, then bypassed
+        control.set_slots(&[
+            slot(StageKind::ReplayGain, false),
+            slot(StageKind::Volume, false),
+        ]);
+        let fade = run(&mut chain, 20);
+        assert!(fade[0] > 0.9);
+        assert!(fade.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(fade[12..].iter().all(|&x| x == 0.0));
+        assert_eq!(run(&mut chain, 4), vec![0.0; 4]);
+
+        // Switched back on: reset once, then faded in
+        let before = resets.load(Ordering::Relaxed);
+        control.set_slots(&[
+            slot(StageKind::ReplayGain, true),
+            slot(StageKind::Volume, false),
+        ]);
+        let fade = run(&mut chain, 20);
+        assert_eq!(resets.load(Ordering::Relaxed), before + 1);
+        assert!(fade[0] < 0.1);
+        assert!(fade.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(fade[12..].iter().all(|&x| x == 1.0));
+    }
+
+    #[test]
+    fn fade_spans_buffers_larger_than_the_scratch() {
+        let (mut chain, control, _) = chain(&[slot(StageKind::ReplayGain, true)]);
+
+        control.set_slots(&[slot(StageKind::ReplayGain, false)]);
+        let data = run(&mut chain, DRY_FRAMES * 2 + 5);
+
+        assert!(data[0] > 0.9);
+        assert!(data[20..].iter().all(|&x| x == 0.0));
+    }
+}
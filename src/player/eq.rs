@@ -0,0 +1,303 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU32, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::dsp::{DspStage, StageKind};
+
+/// Centre frequencies of the graphic equalizer bands, in Hz
+pub const EQ_BANDS: [f32; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+
+/// Largest boost or cut a band accepts, in dB
+pub const MAX_BAND_DB: f32 = 12.0;
+
+/// Bandwidth of each peaking filter, about one octave
+const BAND_Q: f32 = std::f32::consts::SQRT_2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EqPreset {
+    pub name: String,
+    pub gains: [f32; 10],
+}
+
+impl EqPreset {
+    fn new(name: &str, gains: [f32; 10]) -> Self {
+        Self {
+            name: name.to_string(),
+            gains,
+        }
+    }
+}
+
+pub fn builtin_presets() -> Vec<EqPreset> {
+    vec![
+        EqPreset::new("Flat", [0.0; 10]),
+        EqPreset::new("Rock", [5.0, 4.0, 3.0, 1.0, -1.0, -1.0, 1.0, 3.0, 4.0, 5.0]),
+        EqPreset::new(
+            "Vocal",
+            [-3.0, -3.0, -2.0, 0.0, 2.0, 4.0, 4.0, 3.0, 1.0, 0.0],
+        ),
+        EqPreset::new(
+            "Bass Boost",
+            [7.0, 6.0, 5.0, 3.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        ),
+    ]
+}
+
+/// Band gains shared between the player and the stream callback
+#[derive(Clone, Default)]
+pub struct EqControl {
+    /// Gain per band in dB, stored as f32 bits
+    gains: Arc<[AtomicU32; 10]>,
+
+    /// Bumped on every change so the callback knows to recompute its filters
+    generation: Arc<AtomicU32>,
+}
+
+impl EqControl {
+    pub fn gains(&self) -> [f32; 10] {
+        std::array::from_fn(|band| f32::from_bits(self.gains[band].load(Ordering::Relaxed)))
+    }
+
+    pub fn set_gains(&self, gains: &[f32; 10]) {
+        for (slot, gain) in self.gains.iter().zip(gains) {
+            let gain = gain.clamp(-MAX_BAND_DB, MAX_BAND_DB);
+            slot.store(gain.to_bits(), Ordering::Relaxed);
+        }
+
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    fn generation(&self) -> u32 {
+        self.generation.load(Ordering::Acquire)
+    }
+}
+
+/// Peaking biquad, RBJ audio EQ cookbook coefficients normalised by a0
+#[derive(Clone, Copy, PartialEq)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Biquad {
+    /// Passes audio through unchanged while its memory runs out
+    const UNITY: Self = Self {
+        b0: 1.0,
+        b1: 0.0,
+        b2: 0.0,
+        a1: 0.0,
+        a2: 0.0,
+    };
+
+    fn peaking(freq: f32, gain_db: f32, sample_rate: u32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * std::f32::consts::PI * freq / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * BAND_Q);
+        let cos_w0 = w0.cos();
+
+        let a0 = 1.0 + alpha / a;
+
+        Self {
+            b0: (1.0 + alpha * a) / a0,
+            b1: (-2.0 * cos_w0) / a0,
+            b2: (1.0 - alpha * a) / a0,
+            a1: (-2.0 * cos_w0) / a0,
+            a2: (1.0 - alpha / a) / a0,
+        }
+    }
+}
+
+/// Runs the bands over interleaved audio in the stream callback
+pub struct Equalizer {
+    control: EqControl,
+    generation: Option<u32>,
+    sample_rate: u32,
+    channels: usize,
+
+    /// One filter per band, always in band order so each keeps its memory
+    /// across gain changes. Bands at 0 dB pass audio through.
+    filters: [Biquad; 10],
+
+    /// Transposed direct form II state, `[z1, z2]` per band per channel
+    state: Vec<[f32; 2]>,
+
+    /// Whether any band does something, and whether the previous setting
+    /// did and its filter memory still has to run out
+    active: bool,
+    draining: bool,
+}
+
+impl Equalizer {
+    pub fn new(control: EqControl, sample_rate: u32, channels: usize) -> Self {
+        let channels = channels.max(1);
+
+        Self {
+            control,
+            generation: None,
+            sample_rate,
+            channels,
+            filters: [Biquad::UNITY; 10],
+            state: vec![[0.0; 2]; EQ_BANDS.len() * channels],
+            active: false,
+            draining: false,
+        }
+    }
+
+    /// Recompute the coefficients in place; runs in the stream callback
+    fn update_filters(&mut self) {
+        let nyquist = self.sample_rate as f32 / 2.0;
+        let gains = self.control.gains();
+
+        for ((filter, freq), gain) in self.filters.iter_mut().zip(EQ_BANDS).zip(gains) {
+            *filter = if gain.abs() > 0.01 && freq < nyquist * 0.9 {
+                Biquad::peaking(freq, gain, self.sample_rate)
+            } else {
+                Biquad::UNITY
+            };
+        }
+
+        let was_active = self.active;
+        self.active = self.filters.iter().any(|f| *f != Biquad::UNITY);
+        self.draining |= was_active && !self.active;
+    }
+}
+
+impl DspStage for Equalizer {
+    fn kind(&self) -> StageKind {
+        StageKind::Equalizer
+    }
+
+    fn process(&mut self, data: &mut [f32]) {
+        let generation = self.control.generation();
+        if self.generation != Some(generation) {
+            self.generation = Some(generation);
+            self.update_filters();
+        }
+
+        if !self.active && !self.draining {
+            return;
+        }
+
+        for frame in data.chunks_mut(self.channels) {
+            for (ch, sample) in frame.iter_mut().enumerate() {
+                let mut x = *sample;
+
+                for (band, f) in self.filters.iter().enumerate() {
+                    let z = &mut self.state[band * self.channels + ch];
+
+                    let y = f.b0 * x + z[0];
+                    z[0] = f.b1 * x - f.a1 * y + z[1];
+                    z[1] = f.b2 * x - f.a2 * y;
+
+                    x = y;
+                }
+
+                *sample = x;
+            }
+        }
+
+        // Unity filters empty their memory within two frames
+        if data.len() >= 2 * self.channels {
+            self.draining = false;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state.iter_mut().for_each(|z| *z = [0.0; 2]);
+        self.draining = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+
+    /// Gain of a steady sine at `freq` through the equalizer, as an RMS ratio
+    fn response(eq: &mut Equalizer, freq: f32) -> f32 {
+        let sine: Vec<f32> = (0..RATE as usize / 2)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / RATE as f32).sin() * 0.25)
+            .collect();
+
+        let mut output = sine.clone();
+        eq.reset();
+        eq.process(&mut output);
+
+        // Skip the first half while the filters settle
+        let rms = |x: &[f32]| (x.iter().map(|s| s * s).sum::<f32>() / x.len() as f32).sqrt();
+        let half = sine.len() / 2;
+        rms(&output[half..]) / rms(&sine[half..])
+    }
+
+    fn equalizer(gains: [f32; 10]) -> Equalizer {
+        let control = EqControl::default();
+        control.set_gains(&gains);
+        Equalizer::new(control, RATE, 1)
+    }
+
+    #[test]
+    fn flat_preset_passes_audio_unchanged() {
+        let flat = builtin_presets()
+            .into_iter()
+            .find(|p| p.name == "Flat")
+            .unwrap();
+        let mut eq = equalizer(flat.gains);
+
+        let input: Vec<f32> = (0..4096)
+            .map(|i| ((i * 7919) % 2000) as f32 / 1000.0 - 1.0)
+            .collect();
+        let mut output = input.clone();
+        eq.process(&mut output);
+
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn boosted_band_doubles_its_centre_only() {
+        let mut gains = [0.0; 10];
+        gains[5] = 6.0;
+        let mut eq = equalizer(gains);
+
+        assert!((response(&mut eq, 1000.0) - 2.0).abs() < 0.05);
+
+        // An octave away the band adds about 1 dB, two octaves away it is
+        // gone
+        for freq in [500.0, 2000.0] {
+            let gain = response(&mut eq, freq);
+            assert!((1.0..1.19).contains(&gain), "{freq} Hz: {gain}");
+        }
+        for freq in [62.0, 125.0, 250.0, 4000.0, 8000.0, 16000.0] {
+            let gain = response(&mut eq, freq);
+            assert!((gain - 1.0).abs() < 0.03, "{freq} Hz: {gain}");
+        }
+    }
+
+    #[test]
+    fn back_to_flat_is_exact_again() {
+        let mut gains = [0.0; 10];
+        gains[2] = -9.0;
+        let control = EqControl::default();
+        control.set_gains(&gains);
+        let mut eq = Equalizer::new(control.clone(), RATE, 2);
+
+        let mut data = vec![0.5; 512];
+        eq.process(&mut data);
+
+        control.set_gains(&[0.0; 10]);
+        let mut data = vec![0.5; 512];
+        eq.process(&mut data);
+
+        let mut data = vec![0.5; 512];
+        eq.process(&mut data);
+        assert!(data.iter().all(|&x| x == 0.5));
+    }
+}
@@ -0,0 +1,48 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Why playback could not start
+#[derive(Debug)]
+pub enum PlayError {
+    /// The file could not be opened, probed or decoded
+    Track { path: PathBuf, source: anyhow::Error },
+
+    /// The audio output could not be opened or started
+    Output(anyhow::Error),
+}
+
+impl PlayError {
+    /// The track that could not be played, for track errors
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            PlayError::Track { path, .. } => Some(path),
+            PlayError::Output(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for PlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayError::Track { path, source } => {
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy())
+                    .unwrap_or_else(|| path.to_string_lossy());
+
+                write!(f, "Cannot play {name}: {source}")
+            }
+            PlayError::Output(source) => write!(f, "Audio output unavailable: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for PlayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlayError::Track { source, .. } | PlayError::Output(source) => Some(source.as_ref()),
+        }
+    }
+}
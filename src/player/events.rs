@@ -0,0 +1,185 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{Receiver, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use super::{PlayError, clock::PlaybackClock, queue::TrackLog};
+
+/// Something that happened during playback, in the order it happened
+#[derive(Debug)]
+pub enum PlayerEvent {
+    /// A track became audible, either from `play` or by moving on to the
+    /// queued next track
+    TrackStarted(PathBuf),
+
+    /// A track played to its end. `playback_ended` is set when nothing was
+    /// queued after it, so the output has gone quiet.
+    TrackFinished { path: PathBuf, playback_ended: bool },
+
+    /// The queued crossfade into the next track cannot happen because the
+    /// length of this one is unknown; it plays gaplessly instead
+    CrossfadeUnavailable(PathBuf),
+
+    /// Position within the audible track, as heard
+    PositionChanged(Duration),
+
+    /// Something went wrong after playback started, e.g. the queued next
+    /// track could not be opened
+    Error(PlayError),
+
+    /// The output device went away; playback has to be started again
+    DeviceLost(String),
+}
+
+/// An event tagged with the playback session it belongs to. Every command
+/// that stops or replaces playback starts a new session.
+pub type Stamped = (u64, PlayerEvent);
+
+/// Sends events on behalf of one playback session
+#[derive(Clone)]
+pub struct EventSender {
+    tx: Sender<Stamped>,
+    session: u64,
+}
+
+impl EventSender {
+    pub fn new(tx: Sender<Stamped>, session: u64) -> Self {
+        Self { tx, session }
+    }
+
+    /// Returns `false` once nobody is listening any more
+    pub fn send(&self, event: PlayerEvent) -> bool {
+        self.tx.send((self.session, event)).is_ok()
+    }
+}
+
+/// Receiving end of the player's events. Events left over from playback
+/// that has since been stopped or replaced are dropped.
+pub struct PlayerEvents {
+    rx: Receiver<Stamped>,
+
+    /// Latest session requested through any `PlayerHandle`
+    session: Arc<AtomicU64>,
+}
+
+impl PlayerEvents {
+    pub fn new(rx: Receiver<Stamped>, session: Arc<AtomicU64>) -> Self {
+        Self { rx, session }
+    }
+
+    /// Everything that happened since the last call, oldest first
+    pub fn poll(&self) -> Vec<PlayerEvent> {
+        let current = self.session.load(Ordering::SeqCst);
+
+        self.rx
+            .try_iter()
+            .filter(|(session, _)| *session >= current)
+            .map(|(_, event)| event)
+            .collect()
+    }
+}
+
+/// How often the monitor looks at the playback state
+const MONITOR_TICK: Duration = Duration::from_millis(50);
+
+/// State the stream callback publishes for the monitor to turn into events
+pub struct Watched {
+    pub tracks: Arc<TrackLog>,
+    pub clock: Arc<PlaybackClock>,
+    pub seek_request: Arc<Mutex<Option<f64>>>,
+
+    /// Set by the callback when a queued track became audible
+    pub track_changed: Arc<AtomicBool>,
+
+    /// Set by the callback once decoding is done and everything was played
+    pub ended: Arc<AtomicBool>,
+}
+
+/// Thread that turns the flags the stream callback sets into events, so the
+/// callback itself never has to lock or allocate
+pub struct EventMonitor {
+    running: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl EventMonitor {
+    pub fn spawn(watched: Watched, events: EventSender) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = Arc::clone(&running);
+
+        let handle = thread::spawn(move || {
+            let Some((mut current, _)) = watched.tracks.audible() else {
+                return;
+            };
+
+            if !events.send(PlayerEvent::TrackStarted(current.clone())) {
+                return;
+            }
+
+            let mut reported = None;
+
+            while thread_running.load(Ordering::SeqCst) {
+                let mut sent = Vec::new();
+
+                if watched.track_changed.swap(false, Ordering::SeqCst)
+                    && let Some((path, _)) = watched.tracks.audible()
+                {
+                    log::debug!("Gapless transition to {:?}", path);
+                    let finished = std::mem::replace(&mut current, path.clone());
+
+                    sent.push(PlayerEvent::TrackFinished {
+                        path: finished,
+                        playback_ended: false,
+                    });
+                    sent.push(PlayerEvent::TrackStarted(path));
+                    reported = None;
+                }
+
+                // Hold the old position until the device catches up with a
+                // seek, so the display does not jump back in between
+                if watched.seek_request.lock().unwrap().is_none() {
+                    let position = watched.clock.position();
+                    if reported != Some(position) {
+                        sent.push(PlayerEvent::PositionChanged(position));
+                        reported = Some(position);
+                    }
+                }
+
+                let ended = watched.ended.load(Ordering::SeqCst);
+                if ended {
+                    sent.push(PlayerEvent::TrackFinished {
+                        path: current.clone(),
+                        playback_ended: true,
+                    });
+                }
+
+                for event in sent {
+                    if !events.send(event) {
+                        return;
+                    }
+                }
+
+                if ended {
+                    return;
+                }
+
+                thread::park_timeout(MONITOR_TICK);
+            }
+        });
+
+        Self { running, handle }
+    }
+
+    /// Stop watching. No events are sent once this returns.
+    pub fn stop(self) {
+        self.running.store(false, Ordering::SeqCst);
+        self.handle.thread().unpark();
+        let _ = self.handle.join();
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Request from the player to fade the output out before it stops, and the
+/// stream callback's answer once it has. One per playback.
+#[derive(Default)]
+pub struct FadeOut {
+    requested: AtomicBool,
+    done: AtomicBool,
+}
+
+impl FadeOut {
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::SeqCst)
+    }
+}
+
+/// Ramps the output down when it has to go quiet (pause, stop, switching
+/// tracks) and back up on resume or when a new stream starts, instead of
+/// cutting the waveform mid-cycle, which clicks. Runs last in the stream
+/// callback.
+pub struct OutputFade {
+    channels: usize,
+
+    /// Gain change per frame, 1.0 for an instant cut
+    step: f32,
+
+    /// Position along the ramp, 0.0 silent to 1.0 full
+    level: f32,
+}
+
+impl OutputFade {
+    /// Starts silent, so a new stream ramps in rather than starting
+    /// mid-waveform
+    pub fn new(sample_rate: u32, channels: usize, secs: f32) -> Self {
+        let frames = (secs.max(0.0) * sample_rate as f32).max(1.0);
+
+        Self {
+            channels: channels.max(1),
+            step: 1.0 / frames,
+            level: 0.0,
+        }
+    }
+
+    /// Whether the output has faded all the way out
+    pub fn is_silent(&self) -> bool {
+        self.level == 0.0
+    }
+
+    /// Move towards full level if `audible`, else towards silence, and
+    /// scale `data` along the way
+    pub fn process(&mut self, data: &mut [f32], audible: bool, fade_out: &FadeOut) {
+        let target = if audible { 1.0 } else { 0.0 };
+
+        if self.level == target {
+            if !audible {
+                data.fill(0.0);
+                fade_out.done.store(true, Ordering::SeqCst);
+            }
+            return;
+        }
+
+        for frame in data.chunks_mut(self.channels) {
+            self.level = if audible {
+                (self.level + self.step).min(1.0)
+            } else {
+                (self.level - self.step).max(0.0)
+            };
+
+            // Raised cosine, so the ramp starts and ends without a kink
+            let gain = 0.5 - 0.5 * (std::f32::consts::PI * self.level).cos();
+            frame.iter_mut().for_each(|sample| *sample *= gain);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_stream_fades_in() {
+        let mut fade = OutputFade::new(1000, 1, 0.01);
+        let fade_out = FadeOut::default();
+
+        let mut data = vec![1.0; 20];
+        fade.process(&mut data, true, &fade_out);
+
+        // Ten frames of ramp from silence, then full level
+        assert!(data[0] < 0.1);
+        assert!(data.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(data[10..].iter().all(|&x| x == 1.0));
+    }
+
+    #[test]
+    fn paused_stream_stays_silent() {
+        let mut fade = OutputFade::new(1000, 2, 0.01);
+        let fade_out = FadeOut::default();
+
+        let mut data = vec![1.0; 20];
+        fade.process(&mut data, false, &fade_out);
+
+        assert!(fade.is_silent());
+        assert!(data.iter().all(|&x| x == 0.0));
+        assert!(fade_out.is_done());
+    }
+}
@@ -0,0 +1,113 @@
+use super::dsp::{DspStage, StageKind};
+
+/// Highest peak the limiter lets through, about -0.3 dBFS
+const CEILING: f32 = 0.966;
+
+/// How far ahead the limiter looks for peaks, in seconds
+const LOOKAHEAD_SECS: f32 = 0.002;
+
+/// Time for the gain to recover after a peak, in seconds
+const RELEASE_SECS: f32 = 0.05;
+
+/// Lookahead peak limiter that keeps the output from clipping after gain
+/// stages have pushed it past full scale.
+///
+/// Frames are delayed by the lookahead so the gain can already be heading
+/// down by the time a peak comes out, instead of chopping it off.
+pub struct Limiter {
+    channels: usize,
+
+    /// Delay line of `lookahead` frames and the write position in it
+    delay: Vec<f32>,
+    pos: usize,
+    lookahead: usize,
+
+    gain: f32,
+
+    /// Lowest gain a peak inside the delay line needs, and the per-frame step
+    /// that gets there before that peak leaves the line
+    target: f32,
+    attack_step: f32,
+
+    /// Frames until the last detected peak has left the delay line
+    hold: usize,
+
+    release_step: f32,
+}
+
+impl Limiter {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let channels = channels.max(1);
+        let lookahead = ((LOOKAHEAD_SECS * sample_rate as f32) as usize).max(1);
+
+        Self {
+            channels,
+            delay: vec![0.0; lookahead * channels],
+            pos: 0,
+            lookahead,
+            gain: 1.0,
+            target: 1.0,
+            attack_step: 0.0,
+            hold: 0,
+            release_step: 1.0 / (RELEASE_SECS * sample_rate as f32),
+        }
+    }
+}
+
+impl DspStage for Limiter {
+    fn kind(&self) -> StageKind {
+        StageKind::Limiter
+    }
+
+    fn process(&mut self, frames: &mut [f32]) {
+        for frame in frames.chunks_mut(self.channels) {
+            let peak = frame.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+
+            if peak > CEILING {
+                let needed = CEILING / peak;
+
+                if needed < self.target {
+                    self.target = needed;
+                    self.attack_step = self
+                        .attack_step
+                        .max((self.gain - needed) / self.lookahead as f32);
+                }
+
+                self.hold = self.lookahead;
+            }
+
+            if self.gain > self.target {
+                self.gain = (self.gain - self.attack_step).max(self.target);
+            } else if self.hold == 0 {
+                self.gain = (self.gain + self.release_step).min(1.0);
+                self.target = self.gain;
+                self.attack_step = 0.0;
+            }
+            self.hold = self.hold.saturating_sub(1);
+
+            let slot = &mut self.delay[self.pos * self.channels..][..self.channels];
+            for (sample, delayed) in frame.iter_mut().zip(slot.iter_mut()) {
+                let out = *delayed * self.gain;
+                *delayed = *sample;
+
+                // The envelope is approximate; never let a stray peak through
+                *sample = out.clamp(-CEILING, CEILING);
+            }
+
+            self.pos = (self.pos + 1) % self.lookahead;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.delay.iter_mut().for_each(|s| *s = 0.0);
+        self.pos = 0;
+        self.gain = 1.0;
+        self.target = 1.0;
+        self.attack_step = 0.0;
+        self.hold = 0;
+    }
+
+    fn latency_frames(&self) -> usize {
+        self.lookahead
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+/// Integration time of the RMS reading, in seconds
+const RMS_SECS: f32 = 0.3;
+
+/// Level of one output channel since the last read
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChannelLevel {
+    /// Highest absolute sample, 1.0 being full scale
+    pub peak: f32,
+    pub rms: f32,
+
+    /// Whether a sample reached full scale, so the device clipped it
+    pub clipped: bool,
+}
+
+#[derive(Default)]
+struct Channel {
+    peak: AtomicU32,
+    mean_square: AtomicU32,
+    clipped: AtomicBool,
+}
+
+/// Left and right levels of the audio the stream callback hands to the
+/// device, after the whole DSP chain. The callback only stores atomics;
+/// peaks and clips are held until the UI reads them.
+#[derive(Clone, Default)]
+pub struct LevelMeter {
+    channels: Arc<[Channel; 2]>,
+}
+
+impl LevelMeter {
+    /// Levels since the last call, left then right
+    pub fn take(&self) -> [ChannelLevel; 2] {
+        self.channels.each_ref().map(|channel| ChannelLevel {
+            peak: f32::from_bits(channel.peak.swap(0, Ordering::Relaxed)),
+            rms: f32::from_bits(channel.mean_square.load(Ordering::Relaxed)).sqrt(),
+            clipped: channel.clipped.swap(false, Ordering::Relaxed),
+        })
+    }
+
+    /// Measure interleaved `data` at `sample_rate`. Mono feeds both sides;
+    /// beyond stereo only the front pair is measured.
+    pub fn write(&self, data: &[f32], channels: usize, sample_rate: u32) {
+        let channels = channels.max(1);
+        let frames = data.len() / channels;
+        if frames == 0 {
+            return;
+        }
+
+        // Exponential average, so the reading does not depend on the
+        // callback's buffer size
+        let smoothing = 1.0 - (-(frames as f32) / (RMS_SECS * sample_rate as f32)).exp();
+
+        for (side, channel) in self.channels.iter().enumerate() {
+            let offset = side.min(channels - 1);
+
+            let mut peak = 0.0f32;
+            let mut sum = 0.0;
+            for frame in data.chunks_exact(channels) {
+                let sample = frame[offset];
+                peak = peak.max(sample.abs());
+                sum += sample * sample;
+            }
+
+            let held = f32::from_bits(channel.peak.load(Ordering::Relaxed));
+            channel.peak.store(held.max(peak).to_bits(), Ordering::Relaxed);
+
+            let mean_square = f32::from_bits(channel.mean_square.load(Ordering::Relaxed));
+            let mean_square = mean_square + (sum / frames as f32 - mean_square) * smoothing;
+            channel.mean_square.store(mean_square.to_bits(), Ordering::Relaxed);
+
+            if peak >= 1.0 {
+                channel.clipped.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Drop the RMS reading, e.g. once playback stops
+    pub fn clear(&self) {
+        for channel in self.channels.iter() {
+            channel.mean_square.store(0, Ordering::Relaxed);
+        }
+    }
+}
@@ -1,266 +1,772 @@
+mod actor;
+mod channels;
+mod clock;
+mod crossfade;
+mod crossfeed;
 mod decoder;
+mod dsp;
+mod eq;
+mod error;
+mod events;
+mod fade;
+mod limiter;
+mod meter;
 mod output;
+mod queue;
+mod replaygain;
+mod resample;
+mod source;
+mod stereo;
+mod stretch;
+mod tap;
+mod volume;
+
+pub use actor::{PlayerCommand, PlayerHandle, PlayerState};
+pub use crossfade::{FadeCurve, MAX_CROSSFADE_SECS};
+pub use decoder::{interleave, open_decoder};
+pub use dsp::{DspChainControl, DspStage, StageKind, StageSlot, default_slots, normalize_slots};
+pub use eq::{EQ_BANDS, EqControl, EqPreset, MAX_BAND_DB, builtin_presets};
+pub use error::PlayError;
+pub use meter::LevelMeter;
+pub use events::{PlayerEvent, PlayerEvents};
+pub use output::{
+    DeviceSelection, OutputDevice, SinkConfig, device_available, list_output_devices,
+};
+pub use replaygain::ReplayGainSettings;
+pub use resample::ResampleQuality;
+pub use stereo::StereoControl;
+pub use stretch::{MAX_SPEED, MIN_SPEED};
+pub use tap::OutputTap;
+pub use volume::VolumeControl;
+
+use clock::PlaybackClock;
+use crossfade::{Crossfade, CrossfadeSpec};
+use crossfeed::Crossfeed;
+use dsp::DspChain;
+use eq::Equalizer;
+use events::{EventMonitor, EventSender, Stamped, Watched};
+use fade::{FadeOut, OutputFade};
+use limiter::Limiter;
+use output::{AudioSink, SinkFormat};
+use queue::{SampleRing, TrackLog};
+use replaygain::{ReplayGainStage, TrackGain};
+use source::{OutputFormat, Source};
+use stereo::StereoImage;
+use stretch::{SpeedControl, TimeStretch};
+use volume::VolumeRamp;
 
 use crate::player::thread::JoinHandle;
 
 use std::{
-    fs::File,
     path::{Path, PathBuf},
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    sync::mpsc::{self, Sender},
     sync::{Arc, Mutex},
     thread,
-    time::Duration,
-};
-
-use cpal::{
-    Stream,
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-};
-use symphonia::core::{
-    audio::{AudioBufferRef, Signal},
-    codecs::{CODEC_TYPE_NULL, DecoderOptions},
-    formats::FormatOptions,
-    io::MediaSourceStream,
-    meta::MetadataOptions,
+    time::{Duration, Instant},
 };
 
-use symphonia::default::{get_codecs, get_probe};
-
 use log;
 
-use std::collections::VecDeque;
-
 pub struct Player {
-    pub current_path: Option<PathBuf>,
     pub is_playing: bool,
+
+    /// Decode thread of the current playback, and the flag that ends it
     pub handle: Option<JoinHandle<()>>,
-    stream: Option<Stream>,
-    buffer: Arc<Mutex<Vec<f32>>>,
-    pub autoplay_trigger: Arc<AtomicBool>,
+    cancel: Arc<AtomicBool>,
+
+    /// Where the output goes, opened on first play
+    output: Option<Box<dyn AudioSink>>,
+
+    /// Which sink to open
+    pub sink: SinkConfig,
+
+    /// Sound card for the cpal sink, `None` for the system default
+    device: Option<DeviceSelection>,
+
+    /// Set by the stream callback once everything decoded has been played
+    ended: Arc<AtomicBool>,
     pub is_decoder_done: Arc<AtomicBool>,
     pub is_paused: bool,
     pub paused_flag: Arc<AtomicBool>,
+
+    /// Asks the stream callback to fade out before playback stops
+    fade_out: Arc<FadeOut>,
+
+    /// Pending seek target in seconds, picked up by the decode thread
+    seek_request: Arc<Mutex<Option<f64>>>,
+
+    /// Track the decode thread continues with once the current one ends
+    next_track: Arc<Mutex<Option<NextTrack>>>,
+
+    /// A-B loop within the current track, in seconds
+    loop_region: Arc<Mutex<Option<(f64, f64)>>>,
+
+    /// Tracks handed to the current stream, and which one is reaching the device
+    tracks: Arc<TrackLog>,
+
+    /// Set by the stream callback when playback crosses into the next track
+    track_changed: Arc<AtomicBool>,
+
+    /// Times the stream callback ran out of decoded audio, since startup
+    underruns: Arc<AtomicU64>,
+
+    /// Position of the audible track, kept by the stream callback
+    clock: Arc<PlaybackClock>,
+
+    /// Playback events, sent by the monitor and decode threads and stamped
+    /// with `session`, which the actor sets before each play or stop
+    events_tx: Sender<Stamped>,
+    session: u64,
+    monitor: Option<EventMonitor>,
+
+    /// Interpolation used when the device rate differs from the file's
+    pub resample_quality: ResampleQuality,
+
+    /// Overlap between consecutive tracks in autoplay, 0 for none
+    pub crossfade_secs: f32,
+    pub crossfade_curve: FadeCurve,
+
+    /// Length of the fade on pause, resume and stop, 0 to cut instantly
+    pub fade_secs: f32,
+
+    /// Software volume and mute, applied in the stream callback
+    pub volume: VolumeControl,
+
+    /// Balance, mono downmix and channel swap, applied in the stream callback
+    pub stereo: StereoControl,
+
+    /// Graphic equalizer band gains, applied in the stream callback
+    pub eq: EqControl,
+
+    /// Order and on/off state of the DSP stages run in the stream callback
+    pub dsp: DspChainControl,
+
+    /// Copy of the audio leaving the stream callback, for visualizers
+    pub tap: OutputTap,
+
+    /// Peak and RMS levels of the same audio, for the footer meters
+    pub meter: LevelMeter,
+
+    /// Tempo, applied by the decode thread without changing pitch
+    speed: SpeedControl,
+}
+
+/// Decoded audio buffered ahead of the device, in seconds
+const BUFFER_SECS: f32 = 1.0;
+
+/// How long the decode thread waits before retrying when the buffer is full
+const FULL_WAIT: Duration = Duration::from_millis(5);
+
+/// How often `stop` checks whether the fade-out has finished, and how much
+/// longer than the fade it waits for a callback that may never come
+const FADE_POLL: Duration = Duration::from_millis(2);
+const FADE_MARGIN: Duration = Duration::from_millis(100);
+
+/// Queue a mark `ahead` samples past what is pushed next, waiting if the
+/// ring already holds too many
+fn mark(ring: &SampleRing, cancel: &AtomicBool, seq: u64, gain: f32, secs: f64, ahead: usize) {
+    while !ring.mark(seq, gain, secs, ahead) {
+        if cancel.load(Ordering::SeqCst) {
+            return;
+        }
+        thread::sleep(FULL_WAIT);
+    }
+}
+
+/// A track queued to follow the current one
+struct NextTrack {
+    path: PathBuf,
+    gain: f32,
+    crossfade: Option<CrossfadeSpec>,
 }
 
 impl Player {
     pub fn new() -> Self {
         Self {
-            current_path: None,
             is_playing: false,
-            stream: None,
+            output: None,
+            sink: SinkConfig::default(),
+            device: None,
             handle: None,
-            buffer: Arc::new(Mutex::new(Vec::new())),
-            autoplay_trigger: Arc::new(AtomicBool::new(false)),
+            cancel: Arc::new(AtomicBool::new(false)),
+            ended: Arc::new(AtomicBool::new(false)),
             is_decoder_done: Arc::new(AtomicBool::new(false)),
             is_paused: false,
             paused_flag: Arc::new(AtomicBool::new(false)),
+            fade_out: Arc::new(FadeOut::default()),
+            seek_request: Arc::new(Mutex::new(None)),
+            next_track: Arc::new(Mutex::new(None)),
+            loop_region: Arc::new(Mutex::new(None)),
+            tracks: Arc::new(TrackLog::new(PathBuf::new(), 1.0)),
+            underruns: Arc::new(AtomicU64::new(0)),
+            clock: Arc::new(PlaybackClock::new(44100, 2, 1.0)),
+            // Nobody listens until `PlayerHandle::spawn` connects the player
+            events_tx: mpsc::channel().0,
+            session: 0,
+            monitor: None,
+            track_changed: Arc::new(AtomicBool::new(false)),
+            resample_quality: ResampleQuality::default(),
+            crossfade_secs: 0.0,
+            crossfade_curve: FadeCurve::default(),
+            fade_secs: 0.03,
+            volume: VolumeControl::new(100),
+            stereo: StereoControl::default(),
+            eq: EqControl::default(),
+            dsp: DspChainControl::default(),
+            tap: OutputTap::default(),
+            meter: LevelMeter::default(),
+            speed: SpeedControl::default(),
         }
     }
 
-    pub fn play(&mut self, path: &Path) {
+    /// Start playing `path` from the beginning, scaled by the linear
+    /// ReplayGain factor `gain`. On error nothing is playing afterwards.
+    pub fn play(&mut self, path: &Path, gain: f32) -> Result<(), PlayError> {
         self.stop(); // Stop any current playback
 
-        self.autoplay_trigger.store(false, Ordering::SeqCst);
         self.is_decoder_done.store(false, Ordering::SeqCst);
 
-        let file = File::open(path).expect("Failed to open file");
-        let mss = MediaSourceStream::new(Box::new(file), Default::default());
-
-        let probed = get_probe()
-            .format(
-                &Default::default(),
-                mss,
-                &FormatOptions::default(),
-                &MetadataOptions::default(),
-            )
-            .expect("Unsupported format");
-
-        let mut format = probed.format;
-
-        let track = format
-            .tracks()
-            .iter()
-            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-            .expect("No supported audio track found");
-
-        let mut decoder = get_codecs()
-            .make(&track.codec_params, &DecoderOptions::default())
-            .expect("Unsupported codec");
-
-        let sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
-        let channels = track.codec_params.channels.unwrap().count();
-
-        // Create CPAL output stream
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .expect("No output device available");
-
-        let config = cpal::StreamConfig {
-            channels: channels as u16,
-            sample_rate: cpal::SampleRate(sample_rate),
-            buffer_size: cpal::BufferSize::Default,
+        // Fresh shared state so a decode thread left over from the previous
+        // track cannot pick up requests meant for this one
+        self.ended = Arc::new(AtomicBool::new(false));
+        self.cancel = Arc::new(AtomicBool::new(false));
+        self.track_changed = Arc::new(AtomicBool::new(false));
+        self.seek_request = Arc::new(Mutex::new(None));
+        self.next_track = Arc::new(Mutex::new(None));
+        self.loop_region = Arc::new(Mutex::new(None));
+        self.fade_out = Arc::new(FadeOut::default());
+        self.tracks = Arc::new(TrackLog::new(path.to_path_buf(), gain));
+
+        let sink = match &mut self.output {
+            Some(sink) => sink,
+            None => self
+                .output
+                .insert(self.sink.open(self.device.as_ref()).map_err(PlayError::Output)?),
         };
-        let buffer = Arc::new(Mutex::new(Vec::<f32>::new()));
-        let buffer_clone = Arc::clone(&buffer);
 
-        let sample_buf = Arc::new(Mutex::new(VecDeque::<f32>::new()));
-        let sample_buf_clone = Arc::clone(&sample_buf);
+        // Run the sink at its own rate and channel count, and convert each file to it
+        let SinkFormat {
+            sample_rate: output_rate,
+            channels: output_channels,
+        } = sink.format();
 
-        let autoplay_trigger = Arc::clone(&self.autoplay_trigger);
+        let speed = self.speed.get();
+        self.clock = Arc::new(PlaybackClock::new(output_rate, output_channels, speed));
+
+        let output = OutputFormat {
+            sample_rate: output_rate,
+            channels: output_channels,
+            resample_quality: self.resample_quality,
+        };
+
+        let mut source = Source::open(path, output, gain).map_err(|source| PlayError::Track {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let capacity = (BUFFER_SECS * output_rate as f32) as usize * output_channels;
+        let ring = Arc::new(SampleRing::new(capacity));
+        let ring_clone = Arc::clone(&ring);
+
+        let ended = Arc::clone(&self.ended);
         let decoder_done = Arc::clone(&self.is_decoder_done);
         let decoder_done_for_thread = Arc::clone(&self.is_decoder_done);
         let paused_flag = Arc::clone(&self.paused_flag);
+        let tracks = Arc::clone(&self.tracks);
+        let track_changed = Arc::clone(&self.track_changed);
+        let underruns = Arc::clone(&self.underruns);
+        let clock = Arc::clone(&self.clock);
+        let tap = self.tap.clone();
+        tap.set_sample_rate(output_rate);
+        let meter = self.meter.clone();
+        let fade_out = Arc::clone(&self.fade_out);
+        let mut fade = OutputFade::new(output_rate, output_channels, self.fade_secs);
+
+        let track_gain = TrackGain::new(gain);
+        let stages: Vec<Box<dyn DspStage>> = vec![
+            Box::new(ReplayGainStage::new(track_gain.clone())),
+            Box::new(Equalizer::new(self.eq.clone(), output_rate, output_channels)),
+            Box::new(Crossfeed::new(output_rate, output_channels)),
+            Box::new(StereoImage::new(self.stereo.clone(), output_rate, output_channels)),
+            Box::new(VolumeRamp::new(self.volume.clone(), output_rate, output_channels)),
+            Box::new(Limiter::new(output_rate, output_channels)),
+        ];
+        let mut chain = DspChain::new(self.dsp.clone(), output_rate, output_channels, stages);
+
+        // Runs on the real-time audio thread: no locks, no allocation, no logging
+        let mut audible_seq = 0;
+        let render = move |data: &mut [f32]| {
+            let audible = !paused_flag.load(Ordering::SeqCst) && !fade_out.is_requested();
+
+            // Once faded out, hold the stream where it is
+            if !audible && fade.is_silent() {
+                fade.process(data, false, &fade_out);
+                tap.write(data, output_channels);
+                meter.write(data, output_channels, output_rate);
+                return;
+            }
+
+            let popped = ring_clone.pop_into(data);
+
+            if let Some(flushed) = &popped.flushed {
+                chain.reset();
+                clock.rebase(flushed.at, flushed.secs);
+                clock.set_speed(flushed.speed);
+            }
+
+            // A mark for the audible track is a jump within it, e.g. a loop
+            let mut boundary = None;
+            if let Some(started) = &popped.started {
+                clock.rebase(started.at, started.secs);
+
+                if started.seq != audible_seq {
+                    audible_seq = started.seq;
+                    tracks.set_audible(started.seq);
+                    track_changed.store(true, Ordering::SeqCst);
+                    boundary = Some((started.offset.min(data.len()), started.gain));
+                }
+            }
+
+            clock.advance_to(popped.read);
+
+            // The new track's gain starts at its first sample, so the end of
+            // the previous track in the same buffer keeps its own
+            match boundary {
+                Some((offset, gain)) => {
+                    let (before, after) = data.split_at_mut(offset);
+                    chain.process(before);
+                    track_gain.set(gain);
+                    chain.process(after);
+                }
+                None => chain.process(data),
+            }
+            fade.process(data, audible, &fade_out);
+            tap.write(data, output_channels);
+            meter.write(data, output_channels, output_rate);
+
+            let done = decoder_done.load(Ordering::SeqCst);
+            if popped.short && popped.flushed.is_none() && !done {
+                underruns.fetch_add(1, Ordering::Relaxed);
+            }
+
+            if ring_clone.is_empty() && done {
+                ended.store(true, Ordering::SeqCst);
+            }
+        };
+
+        let events = EventSender::new(self.events_tx.clone(), self.session);
+
+        let lost_events = events.clone();
+        let on_lost = move |reason: String| {
+            lost_events.send(PlayerEvent::DeviceLost(reason));
+        };
 
-        let stream = device
-            .build_output_stream(
-                &config,
-                move |data: &mut [f32], _| {
-                    let mut buf = sample_buf_clone.lock().unwrap();
+        sink.start(Box::new(render), Box::new(on_lost))
+            .map_err(PlayError::Output)?;
 
-                    if paused_flag.load(Ordering::SeqCst) {
-                        for sample in data.iter_mut() {
-                            *sample = 0.0;
+        self.is_playing = true;
+
+        self.monitor = Some(EventMonitor::spawn(
+            Watched {
+                tracks: Arc::clone(&self.tracks),
+                clock: Arc::clone(&self.clock),
+                seek_request: Arc::clone(&self.seek_request),
+                track_changed: Arc::clone(&self.track_changed),
+                ended: Arc::clone(&self.ended),
+            },
+            events.clone(),
+        ));
+
+        // Spawn decoding thread
+        let seek_request = Arc::clone(&self.seek_request);
+        let next_track = Arc::clone(&self.next_track);
+        let tracks = Arc::clone(&self.tracks);
+        let cancel = Arc::clone(&self.cancel);
+        let speed_control = self.speed.clone();
+        let loop_region = Arc::clone(&self.loop_region);
+        let dsp = self.dsp.clone();
+        let handle = thread::spawn(move || {
+            let mut samples = Vec::new();
+
+            // Sequence number of the track in `source`
+            let mut current_seq = 0;
+            let mut stretch = TimeStretch::new(output.sample_rate, output.channels, speed);
+
+            // Outgoing track while fading into `source`, and the crossfade
+            // `source` was entered with in case it has to be queued again
+            let mut crossfade: Option<Crossfade> = None;
+            let mut entered_with: Option<CrossfadeSpec> = None;
+
+            // Track last reported as having no known length to crossfade out of
+            let mut unknown_length_seq = None;
+
+            let cancelled = || cancel.load(Ordering::SeqCst);
+
+            while !cancelled() {
+                // Leave the request in place until the flush is queued, so
+                // the position reported meanwhile stays on the target
+                let pending = *seek_request.lock().unwrap();
+                if let Some(secs) = pending {
+                    crossfade = None;
+                    let mut reopened = false;
+
+                    // If we already moved on to decoding the next track, go back to
+                    // the audible one and hand the next track back for later
+                    if current_seq != tracks.audible_seq()
+                        && let Some((audible, audible_gain)) = tracks.audible()
+                    {
+                        match Source::open(&audible, output, audible_gain) {
+                            Ok(audible_source) => {
+                                current_seq = tracks.audible_seq();
+                                let ahead = std::mem::replace(&mut source, audible_source);
+                                next_track.lock().unwrap().get_or_insert(NextTrack {
+                                    path: ahead.path,
+                                    gain: ahead.gain,
+                                    crossfade: entered_with.take(),
+                                });
+                                reopened = true;
+                            }
+                            Err(source) => {
+                                events.send(PlayerEvent::Error(PlayError::Track {
+                                    path: audible,
+                                    source,
+                                }));
+                            }
                         }
-                        return;
                     }
 
-                    for sample in data.iter_mut() {
-                        *sample = buf.pop_front().unwrap_or(0.0); // Pop from front = correct order
+                    // A speed change arrives as a seek to the current position,
+                    // so everything queued after a flush shares one speed
+                    let speed = speed_control.get();
+
+                    // A failed seek leaves the source where it was, so only
+                    // drop what is queued if decoding actually jumped
+                    let resumed = match source.seek(secs) {
+                        Some(resumed) => Some(resumed),
+                        None if reopened => Some(0.0),
+                        // Cannot seek, but the new speed still needs a clean start
+                        None if speed != stretch.speed() => source.decoded_secs(),
+                        None => None,
+                    };
+
+                    if let Some(resumed) = resumed {
+                        stretch.set_speed(speed);
+                        ring.flush(resumed, speed);
                     }
 
-                    if buf.is_empty() && decoder_done.load(Ordering::SeqCst) {
-                        autoplay_trigger.store(true, Ordering::SeqCst);
+                    let mut request = seek_request.lock().unwrap();
+                    if *request == Some(secs) {
+                        *request = None;
                     }
-                },
-                move |err| log::error!("CPAL stream error: {err}"),
-                None,
-            )
-            .expect("Failed to build output stream");
+                    drop(request);
 
-        stream.play().expect("Failed to play stream");
+                    decoder_done_for_thread.store(false, Ordering::SeqCst);
+                }
 
-        self.is_playing = true;
-        self.current_path = Some(path.to_path_buf());
+                samples.clear();
+                let decoded_from = source.decoded_secs();
+                let mut more = source.decode_next(&mut samples);
+
+                // Reaching B (or the end) inside a loop: cut there and carry on
+                // from A in the same stream
+                let mut looped_to = None;
+                let region = *loop_region.lock().unwrap();
+                if let Some((a, b)) = region
+                    && crossfade.is_none()
+                {
+                    let decoded_to = source.decoded_secs();
+                    if !more || decoded_to.is_some_and(|to| to >= b) {
+                        if let (Some(from), Some(to)) = (decoded_from, decoded_to)
+                            && more
+                            && to > from
+                        {
+                            let frames = samples.len() / output.channels;
+                            let keep = ((b - from) / (to - from)).clamp(0.0, 1.0);
+                            samples.truncate((keep * frames as f64) as usize * output.channels);
+                        }
 
+                        looped_to = source.seek(a);
+                        more = looped_to.is_some();
+                    }
+                }
 
-        // Spawn decoding thread
-        let decode_buffer = Arc::clone(&sample_buf);
-        let handle = thread::spawn(move || {
-            while let Ok(packet) = format.next_packet() {
-                let decoded = match decoder.decode(&packet) {
-                    Ok(decoded) => decoded,
-                    Err(err) => {
-                        log::error!("Decode error: {err}");
-                        continue;
+                if let Some(fade) = &mut crossfade {
+                    fade.mix(&mut samples);
+                    if fade.is_finished() {
+                        crossfade = None;
                     }
-                };
-
-                let spec = decoded.spec();
-                log::debug!(
-                    "Decoded: sample_rate={}, channels={}",
-                    spec.rate,
-                    spec.channels.count()
-                );
-                log::debug!(
-                    "CPAL: sample_rate={}, channels={}",
-                    config.sample_rate.0,
-                    config.channels
-                );
-
-                let mut samples = Vec::new();
-
-                match &decoded {
-                    AudioBufferRef::F32(_) => log::debug!("Decoded buffer format: F32"),
-                    AudioBufferRef::S16(_) => log::debug!("Decoded buffer format: S16"),
-                    AudioBufferRef::U8(_) => log::debug!("Decoded buffer format: U8"),
-                    AudioBufferRef::S24(_) => log::debug!("Decoded buffer format: S24"),
-                    AudioBufferRef::F64(_) => log::debug!("Decoded buffer format: F64"),
-                    AudioBufferRef::S32(_) => log::debug!("Decoded buffer format: S32"),
-                    _ => log::debug!("Decoded buffer format: Unknown/Unsupported"),
                 }
 
-                match decoded {
-                    AudioBufferRef::F32(buf) => {
-                        for frame in 0..buf.frames() {
-                            for ch in 0..buf.spec().channels.count() {
-                                samples.push(buf.chan(ch)[frame]);
-                            }
+                // Let the stretcher run dry at the end of a track, and before
+                // jumping back to A, so the next mark lands right after the
+                // last stretched sample
+                if more && looped_to.is_none() {
+                    stretch.process(&mut samples);
+                } else {
+                    stretch.finish(&mut samples);
+                }
+
+                // Wait for room in the ring; drop the rest if a seek makes it stale
+                let mut pending = &samples[..];
+                while !pending.is_empty() {
+                    let taken = ring.push(pending);
+                    pending = &pending[taken..];
+
+                    if taken == 0 {
+                        if cancelled() {
+                            return;
                         }
-                    }
-                    AudioBufferRef::S16(buf) => {
-                        for frame in 0..buf.frames() {
-                            for ch in 0..buf.spec().channels.count() {
-                                samples.push(buf.chan(ch)[frame] as f32 / i16::MAX as f32);
-                            }
+                        if seek_request.lock().unwrap().is_some() {
+                            break;
                         }
+                        thread::sleep(FULL_WAIT);
                     }
-                    AudioBufferRef::U8(buf) => {
-                        for frame in 0..buf.frames() {
-                            for ch in 0..buf.spec().channels.count() {
-                                samples.push(buf.chan(ch)[frame] as f32 / u8::MAX as f32);
+                }
+
+                if let Some(secs) = looped_to {
+                    mark(&ring, &cancel, current_seq, source.gain, secs, 0);
+                    continue;
+                }
+
+                if more {
+                    // Start fading into the next track once the current one is
+                    // about to end, unless it is looping
+                    let due = if crossfade.is_none() && region.is_none() {
+                        let remaining = source.remaining_secs();
+                        let mut next = next_track.lock().unwrap();
+
+                        let fade_due = match (next.as_ref().and_then(|n| n.crossfade), remaining) {
+                            (Some(spec), Some(remaining)) => remaining <= spec.secs as f64,
+                            (Some(_), None) => {
+                                if unknown_length_seq != Some(current_seq) {
+                                    unknown_length_seq = Some(current_seq);
+                                    log::debug!("No crossfade out of {:?}: length unknown", source.path);
+                                    events.send(PlayerEvent::CrossfadeUnavailable(source.path.clone()));
+                                }
+                                false
                             }
-                        }
-                    }
-                    AudioBufferRef::S24(buf) => {
-                        for frame in 0..buf.frames() {
-                            for ch in 0..buf.spec().channels.count() {
-                                let val = buf.chan(ch)[frame];
-                                let sample_f32 = val.into_i32() as f32 / (1 << 23) as f32;
-                                samples.push(sample_f32);
+                            _ => false,
+                        };
+
+                        if fade_due { next.take() } else { None }
+                    } else {
+                        None
+                    };
+
+                    if let Some(NextTrack {
+                        path,
+                        gain,
+                        crossfade: Some(spec),
+                    }) = due
+                    {
+                        match Source::open(&path, output, gain) {
+                            Ok(next_source) => {
+                                log::debug!("Crossfading into {:?}", path);
+                                // The stretcher still holds the end of what
+                                // was decoded before the fade starts
+                                let seq = tracks.add(path, gain);
+                                let ahead = stretch.latency_frames() * output.channels;
+                                mark(&ring, &cancel, seq, gain, 0.0, ahead);
+                                current_seq = seq;
+
+                                let outgoing = std::mem::replace(&mut source, next_source);
+                                crossfade = Some(Crossfade::new(
+                                    outgoing,
+                                    gain,
+                                    dsp.clone(),
+                                    spec,
+                                    output.sample_rate,
+                                    output.channels,
+                                ));
+                                entered_with = Some(spec);
                             }
-                        }
-                    }
-                    AudioBufferRef::F64(buf) => {
-                        for frame in 0..buf.frames() {
-                            for ch in 0..buf.spec().channels.count() {
-                                samples.push(buf.chan(ch)[frame] as f32);
+                            Err(source) => {
+                                events.send(PlayerEvent::Error(PlayError::Track { path, source }));
                             }
                         }
                     }
-                    AudioBufferRef::S32(buf) => {
-                        for frame in 0..buf.frames() {
-                            for ch in 0..buf.spec().channels.count() {
-                                samples.push(buf.chan(ch)[frame] as f32 / i32::MAX as f32);
-                            }
+
+                    continue;
+                }
+
+                // End of this track: carry straight on into the next one if queued
+                let next = next_track.lock().unwrap().take();
+                if let Some(next) = next {
+                    match Source::open(&next.path, output, next.gain) {
+                        Ok(next_source) => {
+                            let seq = tracks.add(next.path, next.gain);
+                            mark(&ring, &cancel, seq, next.gain, 0.0, 0);
+                            current_seq = seq;
+                            source = next_source;
+                            entered_with = next.crossfade;
+                            continue;
+                        }
+                        Err(source) => {
+                            events.send(PlayerEvent::Error(PlayError::Track {
+                                path: next.path,
+                                source,
+                            }));
                         }
-                    }
-                    _ => {
-                        log::debug!("Unsupported buffer format");
-                        continue;
                     }
                 }
 
-                decode_buffer.lock().unwrap().extend(samples);
+                // Decoding is finished!
+                log::debug!("Finished decoding, setting decoder_done = true");
+                decoder_done_for_thread.store(true, Ordering::SeqCst);
 
-                // simulate streaming rate (may be adjustable)
-                std::thread::sleep(Duration::from_millis(10));
+                // Stay around while the tail is still playing so a seek can rewind
+                // into the track or a late-queued next track can still follow
+                // gaplessly
+                while seek_request.lock().unwrap().is_none() && next_track.lock().unwrap().is_none() {
+                    if ring.is_empty() || cancelled() {
+                        return;
                     }
+                    thread::sleep(FULL_WAIT);
+                }
+                decoder_done_for_thread.store(false, Ordering::SeqCst);
+            }
+        });
+
+        self.handle = Some(handle);
+
+        Ok(())
+    }
+
+    /// Switch to another output device. Playback stops; the device is
+    /// opened when the next track starts.
+    pub fn set_device(&mut self, device: Option<DeviceSelection>) {
+        self.close_output();
+        self.device = device;
+    }
+
+    /// Stop playback and let go of the output, e.g. after the device was
+    /// lost. It is opened again when the next track starts.
+    pub fn close_output(&mut self) {
+        self.stop();
+        self.output = None;
+    }
 
-            // Decoding is finished!
-            log::debug!("Finished decoding, setting decoder_done = true");
-            decoder_done_for_thread.store(true, Ordering::SeqCst);
+    /// Queue the track to continue with once the current one has been decoded,
+    /// so it plays back to back on the same stream. `None` ends playback after
+    /// the current track. The gain is the track's linear ReplayGain factor.
+    /// With `allow_crossfade` the two tracks overlap by the
+    /// configured crossfade length instead.
+    pub fn set_next(&mut self, next: Option<(PathBuf, f32)>, allow_crossfade: bool) {
+        let crossfade = (allow_crossfade && self.crossfade_secs > 0.0).then_some(CrossfadeSpec {
+            secs: self.crossfade_secs.min(MAX_CROSSFADE_SECS),
+            curve: self.crossfade_curve,
         });
 
+        *self.next_track.lock().unwrap() = next.map(|(path, gain)| NextTrack {
+            path,
+            gain,
+            crossfade,
+        });
+    }
 
-        self.handle = Some(handle);
-        self.stream = Some(stream); // store the stream if needed for later stop/resume
-        self.buffer = buffer;
+    /// What the player is doing right now
+    pub fn snapshot(&self) -> PlayerState {
+        let track = if self.is_loaded() {
+            self.tracks.audible().map(|(path, _)| path)
+        } else {
+            None
+        };
+
+        // Report a seek straight away rather than once the device catches up
+        let position = match (&track, *self.seek_request.lock().unwrap()) {
+            (None, _) => Duration::ZERO,
+            (Some(_), Some(secs)) => Duration::from_secs_f64(secs),
+            (Some(_), None) => self.clock.position(),
+        };
+
+        PlayerState {
+            track,
+            paused: self.is_paused,
+            position,
+            underruns: self.underruns.load(Ordering::Relaxed),
+            dsp_latencies: self.dsp.latencies(),
+        }
+    }
+
+    /// Loop between `a` and `b` seconds into the current track, or stop
+    /// looping with `None`. Playback jumps to A straight away unless it is
+    /// already inside the loop.
+    pub fn set_loop(&mut self, region: Option<(f64, f64)>) {
+        let region = region.filter(|(a, b)| b > a);
+        *self.loop_region.lock().unwrap() = region;
+
+        // Whatever is queued may already run past B, so decode again from
+        // a point inside the loop
+        if let Some((a, b)) = region
+            && self.is_loaded()
+        {
+            let position = self.clock.position().as_secs_f64();
+            let target = if (a..b).contains(&position) { position } else { a };
+            *self.seek_request.lock().unwrap() = Some(target);
+        }
+    }
+
+    /// Change the playback speed, keeping the pitch. What is queued gets
+    /// decoded again at the new speed from where playback is now.
+    pub fn set_speed(&mut self, speed: f32) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        if speed == self.speed.get() {
+            return;
+        }
+
+        self.speed.set(speed);
+
+        if self.is_loaded() {
+            let mut request = self.seek_request.lock().unwrap();
+            if request.is_none() {
+                *request = Some(self.clock.position().as_secs_f64());
+            }
+        }
     }
 
     pub fn stop(&mut self) {
-        self.stream = None;
+        // Let the output fade out first; cutting it mid-waveform clicks
+        if self.is_playing {
+            self.fade_out.request();
+
+            let fade = Duration::from_secs_f32(self.fade_secs.max(0.0));
+            let deadline = Instant::now() + fade + FADE_MARGIN;
+            while !self.fade_out.is_done() && Instant::now() < deadline {
+                thread::sleep(FADE_POLL);
+            }
+        }
+
+        if let Some(sink) = self.output.as_mut() {
+            sink.stop();
+        }
+
+        // Wait for the decode thread so rapid track changes cannot pile up
+        // decoders still working through files nobody will hear
+        self.cancel.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take()
+            && handle.join().is_err()
+        {
+            log::error!("Decode thread panicked");
+        }
+
+        if let Some(monitor) = self.monitor.take() {
+            monitor.stop();
+        }
+        self.meter.clear();
         self.is_playing = false;
-        self.current_path = None;
-        self.buffer.lock().unwrap().clear();
     }
 
     pub fn is_loaded(&self) -> bool {
-        self.current_path.is_some()
+        self.is_playing
     }
 
-    pub fn is_done(&self) -> bool {
-        self.buffer.lock().unwrap().is_empty() && self.is_playing
+    /// Seek within the current track. The decode thread flushes any
+    /// buffered samples and resumes from `secs`.
+    pub fn seek(&mut self, secs: f64) {
+        if self.is_loaded() {
+            *self.seek_request.lock().unwrap() = Some(secs.max(0.0));
+        }
     }
 
     pub fn set_paused(&mut self, paused: bool) {
@@ -1,47 +1,244 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+
+/// Fills a buffer of interleaved f32 frames in the sink's format. Called
+/// from the sink's real-time thread, so it must not block.
+pub type Render = Box<dyn FnMut(&mut [f32]) + Send + 'static>;
+
+/// Told why, once, when the device disappears under a running sink
+pub type OnLost = Box<dyn FnMut(String) + Send + 'static>;
 
-#[derive(Default)]
-pub struct AudioOutput {
+/// Rate and channel count a sink consumes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SinkFormat {
     pub sample_rate: u32,
-    pub channels: u16,
+    pub channels: usize,
+}
+
+/// Somewhere the player's output goes. The sink pulls audio by calling the
+/// render function it was started with until it is stopped.
+pub trait AudioSink {
+    fn format(&self) -> SinkFormat;
+
+    /// Start pulling audio from `render`, replacing any previous one.
+    /// Sinks that cannot lose their device never call `on_lost`.
+    fn start(&mut self, render: Render, on_lost: OnLost) -> anyhow::Result<()>;
+
+    /// Stop pulling audio. The sink can be started again afterwards.
+    fn stop(&mut self);
+}
+
+/// Which sink the player opens, stored in `settings.json`
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SinkConfig {
+    /// The default sound card
+    #[default]
+    Cpal,
+
+    /// Discard everything at real-time pace, for running without a sound card
+    Null { sample_rate: u32, channels: usize },
+
+    /// Record the output to a 16-bit WAV file at real-time pace
+    Wav {
+        path: PathBuf,
+        sample_rate: u32,
+        channels: usize,
+    },
+}
+
+impl SinkConfig {
+    /// Open the sink. `device` picks the sound card for the cpal sink,
+    /// `None` for the system default.
+    pub fn open(&self, device: Option<&DeviceSelection>) -> anyhow::Result<Box<dyn AudioSink>> {
+        let sink: Box<dyn AudioSink> = match self {
+            SinkConfig::Cpal => Box::new(CpalSink::open(device)?),
+            SinkConfig::Null {
+                sample_rate,
+                channels,
+            } => Box::new(NullSink::new(SinkFormat {
+                sample_rate: *sample_rate,
+                channels: *channels,
+            })),
+            SinkConfig::Wav {
+                path,
+                sample_rate,
+                channels,
+            } => Box::new(WavSink::create(
+                path,
+                SinkFormat {
+                    sample_rate: *sample_rate,
+                    channels: *channels,
+                },
+            )?),
+        };
+
+        Ok(sink)
+    }
+}
+
+/// An output device remembered by name, since cpal has no stable ids
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceSelection {
+    /// cpal host (audio API) name, e.g. "ALSA" or "JACK"
+    pub host: String,
+    pub name: String,
+}
+
+/// An output device found while listing the available hosts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputDevice {
+    pub selection: DeviceSelection,
+
+    /// Whether this is its host's default output
+    pub is_default: bool,
+}
+
+/// Every output device of every host cpal was built with
+pub fn list_output_devices() -> Vec<OutputDevice> {
+    let mut found = Vec::new();
+
+    for id in cpal::available_hosts() {
+        let host = match cpal::host_from_id(id) {
+            Ok(host) => host,
+            Err(err) => {
+                log::debug!("Audio host {} unavailable: {err}", id.name());
+                continue;
+            }
+        };
+
+        let default_name = host.default_output_device().and_then(|d| d.name().ok());
+
+        let devices = match host.output_devices() {
+            Ok(devices) => devices,
+            Err(err) => {
+                log::warn!("Could not list {} output devices: {err}", id.name());
+                continue;
+            }
+        };
+
+        for device in devices {
+            let Ok(name) = device.name() else {
+                continue;
+            };
+
+            found.push(OutputDevice {
+                is_default: default_name.as_deref() == Some(name.as_str()),
+                selection: DeviceSelection {
+                    host: id.name().to_string(),
+                    name,
+                },
+            });
+        }
+    }
+
+    found
+}
+
+/// Look up a remembered device, `None` if it is not connected any more
+fn find_device(selection: &DeviceSelection) -> Option<cpal::Device> {
+    let id = cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name() == selection.host)?;
+    let host = cpal::host_from_id(id).ok()?;
+
+    host.output_devices()
+        .ok()?
+        .find(|device| device.name().is_ok_and(|name| name == selection.name))
+}
+
+/// Whether a remembered device can currently be opened
+pub fn device_available(selection: &DeviceSelection) -> bool {
+    find_device(selection).is_some()
+}
+
+/// A sound card driven through cpal
+pub struct CpalSink {
+    device: cpal::Device,
+    config: cpal::StreamConfig,
     stream: Option<cpal::Stream>,
-    pub buffer: Arc<Mutex<Vec<f32>>>, // Interleaved stereo samples
 }
 
-impl AudioOutput {
-    pub fn new(sample_rate: u32, channels: u16) -> Self {
-        Self {
-            sample_rate,
+impl CpalSink {
+    /// Open `selection` at its preferred rate and channel count, falling back
+    /// to the default device if it is missing
+    pub fn open(selection: Option<&DeviceSelection>) -> anyhow::Result<Self> {
+        let chosen = selection.and_then(|selection| {
+            let device = find_device(selection);
+            if device.is_none() {
+                log::warn!(
+                    "Output device {:?} ({}) not found, using the default",
+                    selection.name,
+                    selection.host
+                );
+            }
+            device
+        });
+
+        let device = match chosen {
+            Some(device) => device,
+            None => cpal::default_host()
+                .default_output_device()
+                .ok_or_else(|| anyhow::anyhow!("No output device available"))?,
+        };
+
+        log::info!(
+            "Opening output device {:?}",
+            device.name().unwrap_or_default()
+        );
+
+        let (sample_rate, channels) = device
+            .default_output_config()
+            .map(|c| (c.sample_rate().0, c.channels()))
+            .unwrap_or((44100, 2));
+
+        let config = cpal::StreamConfig {
             channels,
+            sample_rate: cpal::SampleRate(sample_rate),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        Ok(Self {
+            device,
+            config,
             stream: None,
-            buffer: Arc::new(Mutex::new(Vec::new())),
-        }
+        })
     }
+}
 
-    pub fn start(&mut self) -> anyhow::Result<()> {
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .ok_or_else(|| anyhow::anyhow!("No output device found"))?;
-
-        let config = device.default_output_config()?.config();
+impl AudioSink for CpalSink {
+    fn format(&self) -> SinkFormat {
+        SinkFormat {
+            sample_rate: self.config.sample_rate.0,
+            channels: self.config.channels as usize,
+        }
+    }
 
-        let channels = self.channels as usize;
-        let buffer = self.buffer.clone();
+    fn start(&mut self, mut render: Render, mut on_lost: OnLost) -> anyhow::Result<()> {
+        self.stop();
 
-        let stream = device.build_output_stream(
-            &config,
-            move |data: &mut [f32], _| {
-                let mut buf = buffer.lock().unwrap();
-                for frame in data.chunks_mut(channels) {
-                    for sample in frame.iter_mut() {
-                        *sample = buf.pop().unwrap_or(0.0);
-                    }
+        let mut lost = false;
+        let stream = self.device.build_output_stream(
+            &self.config,
+            move |data: &mut [f32], _| render(data),
+            move |err| match err {
+                cpal::StreamError::DeviceNotAvailable if !lost => {
+                    lost = true;
+                    on_lost(err.to_string());
                 }
-            },
-            move |err| {
-                log::error!("CPAL stream error: {err}");
+                err => log::error!("CPAL stream error: {err}"),
             },
             None,
         )?;
@@ -51,9 +248,327 @@ impl AudioOutput {
         Ok(())
     }
 
-    /// Append decoded PCM samples to buffer.
-    pub fn push_samples(&mut self, samples: &[f32]) {
-        let mut buf = self.buffer.lock().unwrap();
-        buf.extend_from_slice(samples);
+    fn stop(&mut self) {
+        self.stream = None;
+    }
+}
+
+/// Frames rendered per buffer by the sinks that drive themselves
+const PACED_BUFFER_FRAMES: usize = 1024;
+
+/// Thread that pulls buffers from a render function as fast as a sound card
+/// running at `format` would, and hands them to `write` along with `state`.
+/// Returns `state` when stopped so the caller can finish up with it.
+struct PacedThread<T> {
+    running: Arc<AtomicBool>,
+    handle: JoinHandle<T>,
+}
+
+impl<T: Send + 'static> PacedThread<T> {
+    fn spawn(
+        format: SinkFormat,
+        mut render: Render,
+        mut state: T,
+        write: fn(&mut T, &[f32]),
+    ) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = Arc::clone(&running);
+
+        let handle = thread::spawn(move || {
+            let channels = format.channels.max(1);
+            let mut buffer = vec![0.0; PACED_BUFFER_FRAMES * channels];
+
+            let started = Instant::now();
+            let mut frames: u64 = 0;
+
+            while thread_running.load(Ordering::SeqCst) {
+                render(&mut buffer);
+                write(&mut state, &buffer);
+                frames += PACED_BUFFER_FRAMES as u64;
+
+                let due = Duration::from_secs_f64(frames as f64 / format.sample_rate.max(1) as f64);
+                if let Some(wait) = due.checked_sub(started.elapsed()) {
+                    thread::sleep(wait);
+                }
+            }
+
+            state
+        });
+
+        Self { running, handle }
+    }
+
+    fn stop(self) -> Option<T> {
+        self.running.store(false, Ordering::SeqCst);
+        self.handle.join().ok()
+    }
+}
+
+/// Consumes samples at real-time pace and throws them away
+pub struct NullSink {
+    format: SinkFormat,
+    thread: Option<PacedThread<()>>,
+}
+
+impl NullSink {
+    pub fn new(format: SinkFormat) -> Self {
+        Self {
+            format,
+            thread: None,
+        }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn format(&self) -> SinkFormat {
+        self.format
+    }
+
+    fn start(&mut self, render: Render, _on_lost: OnLost) -> anyhow::Result<()> {
+        self.stop();
+        self.thread = Some(PacedThread::spawn(self.format, render, (), |_, _| {}));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            thread.stop();
+        }
+    }
+}
+
+impl Drop for NullSink {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// 16-bit PCM WAV file, sizes patched into the header as it grows
+struct WavWriter {
+    file: BufWriter<File>,
+    format: SinkFormat,
+    data_bytes: u32,
+}
+
+impl WavWriter {
+    fn create(path: &Path, format: SinkFormat) -> std::io::Result<Self> {
+        let mut writer = Self {
+            file: BufWriter::new(File::create(path)?),
+            format,
+            data_bytes: 0,
+        };
+
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let channels = self.format.channels as u16;
+        let block_align = channels * 2;
+        let byte_rate = self.format.sample_rate * block_align as u32;
+
+        let file = &mut self.file;
+        file.write_all(b"RIFF")?;
+        file.write_all(&(36 + self.data_bytes).to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?; // PCM
+        file.write_all(&channels.to_le_bytes())?;
+        file.write_all(&self.format.sample_rate.to_le_bytes())?;
+        file.write_all(&byte_rate.to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&self.data_bytes.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn write_samples(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.file.write_all(&value.to_le_bytes())?;
+        }
+
+        self.data_bytes = self.data_bytes.saturating_add(samples.len() as u32 * 2);
+        Ok(())
+    }
+
+    /// Rewrite the header with the current length so the file is valid
+    fn finalize(&mut self) -> std::io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.flush()
+    }
+}
+
+/// Write `samples` to a new WAV file, for tests that need a track to open
+#[cfg(test)]
+pub(super) fn write_wav(path: &Path, format: SinkFormat, samples: &[f32]) {
+    let mut writer = WavWriter::create(path, format).unwrap();
+    writer.write_samples(samples).unwrap();
+    writer.finalize().unwrap();
+}
+
+/// Records the output to a WAV file. Every start continues the same file,
+/// so a whole session of tracks ends up in one recording.
+pub struct WavSink {
+    format: SinkFormat,
+
+    /// The file, whenever the paced thread is not busy writing to it
+    writer: Option<WavWriter>,
+    thread: Option<PacedThread<WavWriter>>,
+}
+
+impl WavSink {
+    pub fn create(path: &Path, format: SinkFormat) -> anyhow::Result<Self> {
+        let writer = WavWriter::create(path, format)?;
+        log::info!("Recording output to {:?}", path);
+
+        Ok(Self {
+            format,
+            writer: Some(writer),
+            thread: None,
+        })
+    }
+}
+
+impl AudioSink for WavSink {
+    fn format(&self) -> SinkFormat {
+        self.format
+    }
+
+    fn start(&mut self, render: Render, _on_lost: OnLost) -> anyhow::Result<()> {
+        self.stop();
+
+        let writer = self
+            .writer
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("WAV output was lost"))?;
+
+        self.thread = Some(PacedThread::spawn(
+            self.format,
+            render,
+            writer,
+            |writer, samples| {
+                if let Err(err) = writer.write_samples(samples) {
+                    log::error!("Could not write WAV output: {err}");
+                }
+            },
+        ));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.writer = thread.stop();
+        }
+
+        if let Some(writer) = self.writer.as_mut()
+            && let Err(err) = writer.finalize()
+        {
+            log::error!("Could not finish WAV output: {err}");
+        }
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::player::{Player, PlayerCommand, PlayerEvent, PlayerHandle};
+
+    const RATE: u32 = 8000;
+
+    /// Write a quiet stereo tone `secs` long for the player to open
+    fn write_tone(path: &Path, secs: f32) {
+        let format = SinkFormat {
+            sample_rate: RATE,
+            channels: 2,
+        };
+        let frames = (secs * RATE as f32) as usize;
+        let samples: Vec<f32> = (0..frames)
+            .flat_map(|i| {
+                let x = 0.1 * (i as f32 * 0.1).sin();
+                [x, x]
+            })
+            .collect();
+
+        write_wav(path, format, &samples);
+    }
+
+    fn file_name(path: &Path) -> String {
+        path.file_name().unwrap().to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn autoplay_moves_on_to_the_queued_track() {
+        let dir = std::env::temp_dir().join(format!("shelltrax-null-sink-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("first.wav");
+        let second = dir.join("second.wav");
+        write_tone(&first, 0.3);
+        write_tone(&second, 0.3);
+
+        let (player, events) = PlayerHandle::spawn(|| {
+            let mut player = Player::new();
+            player.sink = SinkConfig::Null {
+                sample_rate: RATE,
+                channels: 2,
+            };
+            player
+        });
+
+        player.send(PlayerCommand::Play {
+            path: first.clone(),
+            gain: 1.0,
+        });
+        player.send(PlayerCommand::Enqueue {
+            next: Some((second.clone(), 1.0)),
+            allow_crossfade: false,
+        });
+
+        let mut seen = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline
+            && !seen.last().is_some_and(|e: &String| e.starts_with("ended"))
+        {
+            for event in events.poll() {
+                match event {
+                    PlayerEvent::TrackStarted(path) => {
+                        seen.push(format!("started {}", file_name(&path)))
+                    }
+                    PlayerEvent::TrackFinished {
+                        path,
+                        playback_ended: false,
+                    } => seen.push(format!("finished {}", file_name(&path))),
+                    PlayerEvent::TrackFinished {
+                        path,
+                        playback_ended: true,
+                    } => seen.push(format!("ended {}", file_name(&path))),
+                    PlayerEvent::Error(err) => panic!("{err}"),
+                    _ => {}
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(
+            seen,
+            [
+                "started first.wav",
+                "finished first.wav",
+                "started second.wav",
+                "ended second.wav",
+            ]
+        );
     }
 }
@@ -0,0 +1,430 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Most track starts that can be queued ahead of playback at once
+const MAX_MARKS: usize = 16;
+
+/// Where in the sample stream a queued track begins, or where playback
+/// jumps within a track
+#[derive(Default)]
+struct Mark {
+    at: AtomicU64,
+    seq: AtomicU64,
+    gain: AtomicU32,
+
+    /// Track time of the sample at `at`, f64 bits
+    secs: AtomicU64,
+}
+
+/// Samples waiting for the output stream: a bounded single-producer,
+/// single-consumer ring between the decode thread and the stream callback.
+///
+/// Nothing here locks, so the callback can never be held up by the decode
+/// thread. Consecutive tracks are written back to back so the stream never
+/// stops between them; a second small ring of marks records where each
+/// queued track begins so the callback can tell when it becomes audible.
+/// Marks also record seamless jumps within a track, e.g. an A-B loop.
+///
+/// Positions are running totals of samples written and read; the slot for
+/// position `n` is `n % capacity`. Samples are stored as f32 bits.
+pub struct SampleRing {
+    samples: Box<[AtomicU32]>,
+    written: AtomicU64,
+    read: AtomicU64,
+
+    marks: [Mark; MAX_MARKS],
+    marks_written: AtomicU64,
+    marks_read: AtomicU64,
+
+    /// Set by the producer to drop everything queued so far. The consumer
+    /// jumps its read positions forward when it sees `flushes` change.
+    flush_to: AtomicU64,
+    flush_marks_to: AtomicU64,
+    flushes: AtomicU64,
+
+    /// Track time of the first sample after the flush, f64 bits, and the
+    /// playback speed from there on, f32 bits
+    flush_secs: AtomicU64,
+    flush_speed: AtomicU32,
+    flushes_seen: AtomicU64,
+}
+
+/// What happened during one `SampleRing::pop_into`
+#[derive(Default)]
+pub struct Popped {
+    /// The track that became audible
+    pub started: Option<Started>,
+
+    /// Queued samples were dropped before this buffer, e.g. for a seek
+    pub flushed: Option<Flushed>,
+
+    /// The ring ran dry and the rest of the buffer was padded with silence
+    pub short: bool,
+
+    /// Read position after this buffer
+    pub read: u64,
+}
+
+pub struct Flushed {
+    /// Read position and track time playback resumed at
+    pub at: u64,
+    pub secs: f64,
+
+    /// Playback speed of everything after the flush
+    pub speed: f32,
+}
+
+pub struct Started {
+    pub seq: u64,
+    pub gain: f32,
+
+    /// Read position of the marked sample, and its track time
+    pub at: u64,
+    pub secs: f64,
+
+    /// Where the marked sample is in the buffer just filled, 0 if it was
+    /// already played
+    pub offset: usize,
+}
+
+impl SampleRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: (0..capacity.max(1)).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicU64::new(0),
+            read: AtomicU64::new(0),
+            marks: Default::default(),
+            marks_written: AtomicU64::new(0),
+            marks_read: AtomicU64::new(0),
+            flush_to: AtomicU64::new(0),
+            flush_marks_to: AtomicU64::new(0),
+            flushes: AtomicU64::new(0),
+            flush_secs: AtomicU64::new(0),
+            flush_speed: AtomicU32::new(1.0f32.to_bits()),
+            flushes_seen: AtomicU64::new(0),
+        }
+    }
+
+    fn capacity(&self) -> u64 {
+        self.samples.len() as u64
+    }
+
+    // ───── Producer side (decode thread) ─────
+
+    /// Write as much of `samples` as fits and return how many were taken
+    pub fn push(&self, samples: &[f32]) -> usize {
+        let written = self.written.load(Ordering::Relaxed);
+        let read = self.read.load(Ordering::Acquire);
+
+        let free = self.capacity() - (written - read);
+        let count = samples.len().min(free as usize);
+
+        for (i, sample) in samples[..count].iter().enumerate() {
+            let slot = ((written + i as u64) % self.capacity()) as usize;
+            self.samples[slot].store(sample.to_bits(), Ordering::Relaxed);
+        }
+
+        self.written
+            .store(written + count as u64, Ordering::Release);
+        count
+    }
+
+    /// Mark the sample `ahead` samples after the next pushed one as `secs`
+    /// into track `seq`: zero for a track start, anything for a jump.
+    /// Returns `false` if too many marks are already waiting.
+    pub fn mark(&self, seq: u64, gain: f32, secs: f64, ahead: usize) -> bool {
+        let marks_written = self.marks_written.load(Ordering::Relaxed);
+        if marks_written - self.marks_read.load(Ordering::Acquire) >= MAX_MARKS as u64 {
+            return false;
+        }
+
+        let mark = &self.marks[(marks_written % MAX_MARKS as u64) as usize];
+        let at = self.written.load(Ordering::Relaxed) + ahead as u64;
+        mark.at.store(at, Ordering::Relaxed);
+        mark.seq.store(seq, Ordering::Relaxed);
+        mark.gain.store(gain.to_bits(), Ordering::Relaxed);
+        mark.secs.store(secs.to_bits(), Ordering::Relaxed);
+
+        self.marks_written
+            .store(marks_written + 1, Ordering::Release);
+        true
+    }
+
+    /// Ask the consumer to drop everything that has not been played yet.
+    /// `secs` is the track time of the next sample pushed, and `speed` the
+    /// playback speed it was stretched to.
+    pub fn flush(&self, secs: f64, speed: f32) {
+        self.flush_secs.store(secs.to_bits(), Ordering::Relaxed);
+        self.flush_speed.store(speed.to_bits(), Ordering::Relaxed);
+        self.flush_to
+            .store(self.written.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.flush_marks_to.store(
+            self.marks_written.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        self.flushes.fetch_add(1, Ordering::Release);
+    }
+
+    // ───── Either side ─────
+
+    pub fn is_empty(&self) -> bool {
+        self.written.load(Ordering::Acquire) <= self.read.load(Ordering::Acquire)
+    }
+
+    // ───── Consumer side (stream callback) ─────
+
+    /// Fill `data` from the front of the ring, padding with silence
+    pub fn pop_into(&self, data: &mut [f32]) -> Popped {
+        let mut popped = Popped::default();
+
+        let mut read = self.read.load(Ordering::Relaxed);
+        let mut marks_read = self.marks_read.load(Ordering::Relaxed);
+
+        let flushes = self.flushes.load(Ordering::Acquire);
+        if flushes != self.flushes_seen.load(Ordering::Relaxed) {
+            self.flushes_seen.store(flushes, Ordering::Relaxed);
+            read = read.max(self.flush_to.load(Ordering::Relaxed));
+            marks_read = marks_read.max(self.flush_marks_to.load(Ordering::Relaxed));
+
+            popped.flushed = Some(Flushed {
+                at: read,
+                secs: f64::from_bits(self.flush_secs.load(Ordering::Relaxed)),
+                speed: f32::from_bits(self.flush_speed.load(Ordering::Relaxed)),
+            });
+        }
+
+        let available = self.written.load(Ordering::Acquire) - read;
+        let count = data.len().min(available as usize);
+
+        for (i, sample) in data[..count].iter_mut().enumerate() {
+            let slot = ((read + i as u64) % self.capacity()) as usize;
+            *sample = f32::from_bits(self.samples[slot].load(Ordering::Relaxed));
+        }
+        data[count..].fill(0.0);
+
+        let start = read;
+        read += count as u64;
+        popped.short = count < data.len();
+
+        let marks_written = self.marks_written.load(Ordering::Acquire);
+        while marks_read < marks_written {
+            let mark = &self.marks[(marks_read % MAX_MARKS as u64) as usize];
+            let at = mark.at.load(Ordering::Relaxed);
+            if at > read {
+                break;
+            }
+
+            popped.started = Some(Started {
+                seq: mark.seq.load(Ordering::Relaxed),
+                gain: f32::from_bits(mark.gain.load(Ordering::Relaxed)),
+                at,
+                secs: f64::from_bits(mark.secs.load(Ordering::Relaxed)),
+                offset: at.saturating_sub(start) as usize,
+            });
+            marks_read += 1;
+        }
+
+        self.marks_read.store(marks_read, Ordering::Release);
+        self.read.store(read, Ordering::Release);
+
+        popped.read = read;
+        popped
+    }
+}
+
+/// Paths of the tracks handed to the ring, by sequence number. Only the
+/// decode thread and the UI touch this; the stream callback just publishes
+/// the sequence number of the audible track.
+pub struct TrackLog {
+    tracks: Mutex<VecDeque<(u64, PathBuf, f32)>>,
+    next_seq: AtomicU64,
+
+    /// Sequence number of the track currently reaching the device
+    audible: AtomicU64,
+}
+
+impl TrackLog {
+    /// Start the log with the first track, which is audible straight away
+    pub fn new(path: PathBuf, gain: f32) -> Self {
+        Self {
+            tracks: Mutex::new(VecDeque::from([(0, path, gain)])),
+            next_seq: AtomicU64::new(1),
+            audible: AtomicU64::new(0),
+        }
+    }
+
+    /// Record a track about to be written to the ring, returning its number
+    pub fn add(&self, path: PathBuf, gain: f32) -> u64 {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        self.tracks.lock().unwrap().push_back((seq, path, gain));
+        seq
+    }
+
+    /// Called from the stream callback when track `seq` becomes audible
+    pub fn set_audible(&self, seq: u64) {
+        self.audible.store(seq, Ordering::Release);
+    }
+
+    pub fn audible_seq(&self) -> u64 {
+        self.audible.load(Ordering::Acquire)
+    }
+
+    /// Path and gain of the track currently reaching the device
+    pub fn audible(&self) -> Option<(PathBuf, f32)> {
+        let seq = self.audible.load(Ordering::Acquire);
+        let mut tracks = self.tracks.lock().unwrap();
+
+        // Earlier tracks are done with for good
+        while tracks.front().is_some_and(|(s, _, _)| *s < seq) {
+            tracks.pop_front();
+        }
+
+        tracks
+            .iter()
+            .find(|(s, _, _)| *s == seq)
+            .map(|(_, path, gain)| (path.clone(), *gain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop(ring: &SampleRing, len: usize) -> (Vec<f32>, Popped) {
+        let mut data = vec![f32::NAN; len];
+        let popped = ring.pop_into(&mut data);
+        (data, popped)
+    }
+
+    #[test]
+    fn samples_come_out_in_order_across_the_wrap() {
+        let ring = SampleRing::new(8);
+
+        assert_eq!(ring.push(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]), 6);
+        assert_eq!(pop(&ring, 4).0, [1.0, 2.0, 3.0, 4.0]);
+
+        // Six free slots, four of them wrapped round to the start
+        assert_eq!(ring.push(&[7.0, 8.0, 9.0, 10.0, 11.0, 12.0]), 6);
+        let (data, popped) = pop(&ring, 8);
+        assert_eq!(data, [5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0]);
+        assert!(!popped.short);
+        assert_eq!(popped.read, 12);
+    }
+
+    #[test]
+    fn push_takes_only_what_fits() {
+        let ring = SampleRing::new(4);
+
+        assert_eq!(ring.push(&[1.0, 2.0, 3.0]), 3);
+        assert_eq!(ring.push(&[4.0, 5.0, 6.0]), 1);
+        assert_eq!(ring.push(&[7.0]), 0);
+
+        pop(&ring, 2);
+        assert_eq!(ring.push(&[5.0, 6.0, 7.0]), 2);
+    }
+
+    #[test]
+    fn running_dry_pads_with_silence() {
+        let ring = SampleRing::new(8);
+        assert!(ring.is_empty());
+
+        ring.push(&[0.5, 0.25]);
+        assert!(!ring.is_empty());
+
+        let (data, popped) = pop(&ring, 4);
+        assert_eq!(data, [0.5, 0.25, 0.0, 0.0]);
+        assert!(popped.short);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn flush_drops_what_was_queued() {
+        let ring = SampleRing::new(16);
+
+        ring.push(&[1.0; 5]);
+        ring.mark(1, 0.5, 0.0, 0);
+        ring.push(&[1.0; 2]);
+        ring.flush(12.5, 1.5);
+        ring.push(&[2.0, 3.0]);
+
+        let (data, popped) = pop(&ring, 4);
+        assert_eq!(data, [2.0, 3.0, 0.0, 0.0]);
+
+        let flushed = popped.flushed.expect("flush not reported");
+        assert_eq!(flushed.at, 7);
+        assert_eq!(flushed.secs, 12.5);
+        assert_eq!(flushed.speed, 1.5);
+
+        // The mark went with the samples it pointed into
+        assert!(popped.started.is_none());
+
+        // Reported once only
+        ring.push(&[4.0]);
+        assert!(pop(&ring, 1).1.flushed.is_none());
+    }
+
+    #[test]
+    fn mark_is_delivered_with_the_sample_it_points_at() {
+        let ring = SampleRing::new(16);
+
+        ring.push(&[0.0; 4]);
+        assert!(ring.mark(3, 0.5, 42.0, 0));
+        ring.push(&[1.0; 4]);
+
+        assert!(pop(&ring, 3).1.started.is_none());
+
+        let started = pop(&ring, 3).1.started.expect("mark not delivered");
+        assert_eq!(started.seq, 3);
+        assert_eq!(started.gain, 0.5);
+        assert_eq!(started.at, 4);
+        assert_eq!(started.secs, 42.0);
+        assert_eq!(started.offset, 1);
+
+        assert!(pop(&ring, 2).1.started.is_none());
+    }
+
+    #[test]
+    fn mark_can_point_past_what_is_pushed() {
+        let ring = SampleRing::new(16);
+
+        ring.push(&[0.0; 2]);
+        assert!(ring.mark(1, 1.0, 0.0, 3));
+        ring.push(&[1.0; 6]);
+
+        assert!(pop(&ring, 4).1.started.is_none());
+
+        let started = pop(&ring, 4).1.started.expect("mark not delivered");
+        assert_eq!(started.at, 5);
+        assert_eq!(started.offset, 1);
+    }
+
+    #[test]
+    fn marks_are_bounded() {
+        let ring = SampleRing::new(4);
+
+        for seq in 0..MAX_MARKS as u64 {
+            assert!(ring.mark(seq, 1.0, 0.0, 0));
+        }
+        assert!(!ring.mark(99, 1.0, 0.0, 0));
+
+        // All of them point at the next sample, so one pop takes them all
+        // and the latest wins
+        let started = pop(&ring, 1).1.started.unwrap();
+        assert_eq!(started.seq, MAX_MARKS as u64 - 1);
+        assert!(ring.mark(99, 1.0, 0.0, 0));
+    }
+
+    #[test]
+    fn track_log_follows_the_audible_track() {
+        let log = TrackLog::new(PathBuf::from("a"), 1.0);
+        let second = log.add(PathBuf::from("b"), 0.5);
+
+        assert_eq!(log.audible(), Some((PathBuf::from("a"), 1.0)));
+
+        log.set_audible(second);
+        assert_eq!(log.audible_seq(), second);
+        assert_eq!(log.audible(), Some((PathBuf::from("b"), 0.5)));
+    }
+}
@@ -0,0 +1,251 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU32, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::dsp::{DspStage, StageKind};
+use crate::library::ReplayGain;
+
+/// Which ReplayGain value is applied during playback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayGainMode {
+    Off,
+    Track,
+    Album,
+    /// Album gain while an album plays in order, track gain otherwise
+    #[default]
+    Auto,
+}
+
+impl ReplayGainMode {
+    pub fn next(self) -> Self {
+        match self {
+            ReplayGainMode::Off => ReplayGainMode::Track,
+            ReplayGainMode::Track => ReplayGainMode::Album,
+            ReplayGainMode::Album => ReplayGainMode::Auto,
+            ReplayGainMode::Auto => ReplayGainMode::Off,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplayGainSettings {
+    pub mode: ReplayGainMode,
+
+    /// Extra gain in dB applied on top of the tag value
+    pub preamp_db: f32,
+
+    /// Lower the gain where the tagged peak would otherwise clip
+    pub prevent_clipping: bool,
+}
+
+impl Default for ReplayGainSettings {
+    fn default() -> Self {
+        Self {
+            mode: ReplayGainMode::default(),
+            preamp_db: 0.0,
+            prevent_clipping: true,
+        }
+    }
+}
+
+impl ReplayGainSettings {
+    /// Linear factor to scale a track's samples by. Tracks without tags play
+    /// unchanged.
+    pub fn factor(&self, tags: &ReplayGain, album_context: bool) -> f32 {
+        let track = (tags.track_gain, tags.track_peak);
+        let album = (tags.album_gain, tags.album_peak);
+
+        let (gain, peak) = match self.mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track => prefer(track, album),
+            ReplayGainMode::Album => prefer(album, track),
+            ReplayGainMode::Auto if album_context => prefer(album, track),
+            ReplayGainMode::Auto => prefer(track, album),
+        };
+
+        let Some(gain) = gain else {
+            return 1.0;
+        };
+
+        let mut factor = 10f32.powf((gain + self.preamp_db) / 20.0);
+
+        if self.prevent_clipping
+            && let Some(peak) = peak.filter(|p| *p > 0.0)
+        {
+            factor = factor.min(1.0 / peak);
+        }
+
+        factor
+    }
+}
+
+/// The first pair that has a gain value
+fn prefer(
+    first: (Option<f32>, Option<f32>),
+    fallback: (Option<f32>, Option<f32>),
+) -> (Option<f32>, Option<f32>) {
+    if first.0.is_some() { first } else { fallback }
+}
+
+/// Linear factor of the track currently reaching the device, stored as f32
+/// bits. The stream callback updates it as playback crosses track boundaries.
+#[derive(Clone)]
+pub struct TrackGain(Arc<AtomicU32>);
+
+impl TrackGain {
+    pub fn new(gain: f32) -> Self {
+        Self(Arc::new(AtomicU32::new(gain.to_bits())))
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, gain: f32) {
+        self.0.store(gain.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Scales the output by the audible track's ReplayGain factor
+pub struct ReplayGainStage {
+    gain: TrackGain,
+}
+
+impl ReplayGainStage {
+    pub fn new(gain: TrackGain) -> Self {
+        Self { gain }
+    }
+}
+
+impl DspStage for ReplayGainStage {
+    fn kind(&self) -> StageKind {
+        StageKind::ReplayGain
+    }
+
+    fn process(&mut self, frames: &mut [f32]) {
+        let gain = self.gain.get();
+
+        if gain != 1.0 {
+            frames.iter_mut().for_each(|sample| *sample *= gain);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags() -> ReplayGain {
+        ReplayGain {
+            track_gain: Some(-6.0),
+            track_peak: Some(0.5),
+            album_gain: Some(-12.0),
+            album_peak: Some(0.9),
+        }
+    }
+
+    fn settings(mode: ReplayGainMode) -> ReplayGainSettings {
+        ReplayGainSettings {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    fn db(factor: f32) -> f32 {
+        20.0 * factor.log10()
+    }
+
+    fn assert_db(factor: f32, expected: f32) {
+        assert!(
+            (db(factor) - expected).abs() < 0.01,
+            "{} dB, expected {expected}",
+            db(factor)
+        );
+    }
+
+    #[test]
+    fn off_and_untagged_play_unchanged() {
+        assert_eq!(settings(ReplayGainMode::Off).factor(&tags(), true), 1.0);
+        assert_eq!(
+            settings(ReplayGainMode::Track).factor(&ReplayGain::default(), false),
+            1.0
+        );
+    }
+
+    #[test]
+    fn track_and_album_modes() {
+        assert_db(settings(ReplayGainMode::Track).factor(&tags(), true), -6.0);
+        assert_db(
+            settings(ReplayGainMode::Album).factor(&tags(), false),
+            -12.0,
+        );
+    }
+
+    #[test]
+    fn auto_mode_follows_album_context() {
+        let auto = settings(ReplayGainMode::Auto);
+
+        assert_db(auto.factor(&tags(), true), -12.0);
+        assert_db(auto.factor(&tags(), false), -6.0);
+    }
+
+    #[test]
+    fn missing_value_falls_back_to_the_other() {
+        let track_only = ReplayGain {
+            album_gain: None,
+            album_peak: None,
+            ..tags()
+        };
+        let album_only = ReplayGain {
+            track_gain: None,
+            track_peak: None,
+            ..tags()
+        };
+
+        assert_db(
+            settings(ReplayGainMode::Album).factor(&track_only, true),
+            -6.0,
+        );
+        assert_db(
+            settings(ReplayGainMode::Track).factor(&album_only, false),
+            -12.0,
+        );
+    }
+
+    #[test]
+    fn preamp_adds_to_the_tag() {
+        let settings = ReplayGainSettings {
+            preamp_db: 4.0,
+            ..settings(ReplayGainMode::Track)
+        };
+
+        assert_db(settings.factor(&tags(), false), -2.0);
+    }
+
+    #[test]
+    fn peak_limits_the_gain() {
+        let loud = ReplayGain {
+            track_gain: Some(6.0),
+            track_peak: Some(0.8),
+            ..Default::default()
+        };
+
+        // +6 dB would take a 0.8 peak to 1.6
+        let clamped = settings(ReplayGainMode::Track).factor(&loud, false);
+        assert!((clamped - 1.25).abs() < 1e-6);
+
+        let unclamped = ReplayGainSettings {
+            prevent_clipping: false,
+            ..settings(ReplayGainMode::Track)
+        };
+        assert_db(unclamped.factor(&loud, false), 6.0);
+
+        // A gain that cannot clip is left alone
+        assert_db(settings(ReplayGainMode::Track).factor(&tags(), false), -6.0);
+    }
+}
@@ -0,0 +1,280 @@
+use serde::{Deserialize, Serialize};
+
+/// Half the number of input frames the sinc kernel looks at
+const SINC_HALF_WIDTH: usize = 16;
+
+/// Interpolation used when converting between sample rates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResampleQuality {
+    /// Linear interpolation, cheapest but dulls the top end
+    Low,
+    /// Cubic Hermite interpolation
+    #[default]
+    Medium,
+    /// Blackman-windowed sinc, band-limited
+    High,
+}
+
+impl ResampleQuality {
+    pub fn next(self) -> Self {
+        match self {
+            ResampleQuality::Low => ResampleQuality::Medium,
+            ResampleQuality::Medium => ResampleQuality::High,
+            ResampleQuality::High => ResampleQuality::Low,
+        }
+    }
+
+    /// Frames of context needed before and after the interpolation point
+    fn taps(self) -> (usize, usize) {
+        match self {
+            ResampleQuality::Low => (0, 1),
+            ResampleQuality::Medium => (1, 2),
+            ResampleQuality::High => (SINC_HALF_WIDTH - 1, SINC_HALF_WIDTH),
+        }
+    }
+}
+
+/// Streaming sample-rate converter for interleaved f32 audio.
+///
+/// Input arrives packet by packet, so the resampler holds back the last few
+/// frames of each call until it has enough look-ahead to interpolate them.
+pub struct Resampler {
+    quality: ResampleQuality,
+    channels: usize,
+
+    /// Input frames advanced per output frame
+    step: f64,
+
+    /// Low-pass cutoff relative to the input Nyquist, below 1.0 when downsampling
+    cutoff: f64,
+
+    /// Interleaved input that has not been fully consumed yet
+    pending: Vec<f32>,
+
+    /// Read position in `pending`, in frames
+    position: f64,
+}
+
+impl Resampler {
+    pub fn new(
+        input_rate: u32,
+        output_rate: u32,
+        channels: usize,
+        quality: ResampleQuality,
+    ) -> Self {
+        let step = input_rate as f64 / output_rate as f64;
+
+        let mut resampler = Self {
+            quality,
+            channels: channels.max(1),
+            step,
+            cutoff: (1.0 / step).min(1.0),
+            pending: Vec::new(),
+            position: 0.0,
+        };
+
+        resampler.reset();
+        resampler
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.step == 1.0
+    }
+
+    /// Drop buffered input, e.g. after a seek
+    pub fn reset(&mut self) {
+        let (before, _) = self.quality.taps();
+
+        self.pending.clear();
+        self.pending.resize(before * self.channels, 0.0);
+        self.position = before as f64;
+    }
+
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        if self.is_passthrough() {
+            output.extend_from_slice(input);
+            return;
+        }
+
+        self.pending.extend_from_slice(input);
+        self.drain(output);
+    }
+
+    /// Emit the frames still held back for look-ahead at the end of a stream
+    pub fn flush(&mut self, output: &mut Vec<f32>) {
+        if self.is_passthrough() {
+            return;
+        }
+
+        let (_, after) = self.quality.taps();
+        self.pending
+            .resize(self.pending.len() + after * self.channels, 0.0);
+        self.drain(output);
+        self.reset();
+    }
+
+    fn drain(&mut self, output: &mut Vec<f32>) {
+        let (before, after) = self.quality.taps();
+        let frames = self.pending.len() / self.channels;
+
+        while (self.position as usize) + after < frames {
+            let base = self.position as usize;
+            let frac = self.position - base as f64;
+
+            for ch in 0..self.channels {
+                output.push(self.interpolate(base, frac, ch));
+            }
+
+            self.position += self.step;
+        }
+
+        // Keep only the context the next call still needs
+        let consumed = (self.position as usize).saturating_sub(before).min(frames);
+        self.pending.drain(..consumed * self.channels);
+        self.position -= consumed as f64;
+    }
+
+    fn sample(&self, frame: usize, ch: usize) -> f32 {
+        self.pending[frame * self.channels + ch]
+    }
+
+    fn interpolate(&self, base: usize, frac: f64, ch: usize) -> f32 {
+        match self.quality {
+            ResampleQuality::Low => {
+                let a = self.sample(base, ch);
+                let b = self.sample(base + 1, ch);
+                a + (b - a) * frac as f32
+            }
+            ResampleQuality::Medium => {
+                let y0 = self.sample(base - 1, ch);
+                let y1 = self.sample(base, ch);
+                let y2 = self.sample(base + 1, ch);
+                let y3 = self.sample(base + 2, ch);
+                let t = frac as f32;
+
+                let c1 = 0.5 * (y2 - y0);
+                let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+                let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+
+                ((c3 * t + c2) * t + c1) * t + y1
+            }
+            ResampleQuality::High => {
+                let half = SINC_HALF_WIDTH as f64;
+                let mut acc = 0.0;
+
+                for k in 0..2 * SINC_HALF_WIDTH {
+                    let frame = base + 1 + k - SINC_HALF_WIDTH;
+
+                    // Distance from the interpolation point to this input frame
+                    let t = frac + half - 1.0 - k as f64;
+                    let weight = self.cutoff * sinc(self.cutoff * t) * blackman(t / half);
+
+                    acc += self.sample(frame, ch) as f64 * weight;
+                }
+
+                acc as f32
+            }
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        let px = std::f64::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+/// Blackman window over -1.0..=1.0
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        return 0.0;
+    }
+
+    let px = std::f64::consts::PI * x;
+    0.42 + 0.5 * px.cos() + 0.08 * (2.0 * px).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUALITIES: [ResampleQuality; 3] = [
+        ResampleQuality::Low,
+        ResampleQuality::Medium,
+        ResampleQuality::High,
+    ];
+
+    /// Feed `input` in packets of `packet` frames, then flush
+    fn run(resampler: &mut Resampler, input: &[f32], channels: usize, packet: usize) -> Vec<f32> {
+        let mut output = Vec::new();
+        for chunk in input.chunks(packet * channels) {
+            resampler.process(chunk, &mut output);
+        }
+        resampler.flush(&mut output);
+        output
+    }
+
+    #[test]
+    fn equal_rates_pass_audio_through() {
+        let input: Vec<f32> = (0..2000).map(|i| (i as f32 * 0.01).sin()).collect();
+
+        for quality in QUALITIES {
+            let mut resampler = Resampler::new(48000, 48000, 2, quality);
+            assert!(resampler.is_passthrough());
+            assert_eq!(run(&mut resampler, &input, 2, 256), input);
+        }
+    }
+
+    #[test]
+    fn output_length_follows_the_rate_ratio() {
+        for (from, to) in [(44100, 48000), (48000, 44100), (22050, 96000)] {
+            for quality in QUALITIES {
+                let frames = from as usize;
+                let input = vec![0.25; frames * 2];
+
+                let mut resampler = Resampler::new(from, to, 2, quality);
+                let output = run(&mut resampler, &input, 2, 1152);
+
+                assert_eq!(output.len() % 2, 0);
+                let produced = output.len() / 2;
+                let expected = to as usize;
+                assert!(
+                    produced.abs_diff(expected) <= 2,
+                    "{from} -> {to} Hz ({quality:?}): {produced} frames, expected {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn constant_signal_stays_constant() {
+        let input = vec![0.5; 4800];
+
+        for quality in QUALITIES {
+            let mut resampler = Resampler::new(44100, 48000, 1, quality);
+            let output = run(&mut resampler, &input, 1, 480);
+
+            // Away from the edges, where the kernel reaches into silence
+            for sample in &output[100..output.len() - 100] {
+                assert!((sample - 0.5).abs() < 0.01, "{quality:?}: {sample}");
+            }
+        }
+    }
+
+    #[test]
+    fn reset_drops_buffered_input() {
+        let mut resampler = Resampler::new(44100, 48000, 1, ResampleQuality::High);
+        let mut output = Vec::new();
+
+        resampler.process(&[1.0; 64], &mut output);
+        resampler.reset();
+        output.clear();
+        resampler.process(&[0.0; 512], &mut output);
+
+        assert!(output.iter().all(|sample| *sample == 0.0));
+    }
+}
@@ -0,0 +1,283 @@
+use std::path::{Path, PathBuf};
+
+use symphonia::core::{
+    audio::Channels,
+    codecs::Decoder,
+    formats::{FormatReader, SeekMode, SeekTo},
+    units::{Time, TimeBase},
+};
+
+use super::channels::ChannelMapper;
+use super::decoder::{interleave, open_decoder};
+use super::resample::{ResampleQuality, Resampler};
+
+/// Output format every source is converted to before it reaches the stream
+#[derive(Debug, Clone, Copy)]
+pub struct OutputFormat {
+    pub sample_rate: u32,
+    pub channels: usize,
+    pub resample_quality: ResampleQuality,
+}
+
+/// One open track, decoded and converted to the output format packet by packet
+pub struct Source {
+    pub path: PathBuf,
+
+    /// Linear ReplayGain factor, applied by the DSP chain once the track is audible
+    pub gain: f32,
+
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    output: OutputFormat,
+    channel_mapper: ChannelMapper,
+    resampler: Resampler,
+
+    /// Timestamp the decoder must reach before samples are audible again after a seek
+    skip_until: u64,
+
+    /// Track length and how far decoding has got, in time base units
+    time_base: Option<TimeBase>,
+    n_frames: Option<u64>,
+    decoded_ts: u64,
+
+    /// Set once the end of the stream has been reached and the resampler flushed
+    finished: bool,
+}
+
+impl Source {
+    pub fn open(path: &Path, output: OutputFormat, gain: f32) -> anyhow::Result<Self> {
+        let (format, decoder, track) = open_decoder(path)?;
+
+        let sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
+        let layout = track
+            .codec_params
+            .channels
+            .unwrap_or(Channels::FRONT_LEFT | Channels::FRONT_RIGHT);
+
+        log::debug!(
+            "Opening {:?}: {sample_rate} Hz -> {} Hz ({:?}), {} channel(s) ({layout:?}) -> {}",
+            path,
+            output.sample_rate,
+            output.resample_quality,
+            layout.count(),
+            output.channels
+        );
+
+        if gain != 1.0 {
+            log::debug!("ReplayGain factor {gain:.3} for {:?}", path);
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            gain,
+            format,
+            decoder,
+            track_id: track.id,
+            output,
+            channel_mapper: ChannelMapper::new(layout, output.channels),
+            resampler: Resampler::new(
+                sample_rate,
+                output.sample_rate,
+                output.channels,
+                output.resample_quality,
+            ),
+            skip_until: 0,
+            time_base: track.codec_params.time_base,
+            n_frames: track.codec_params.n_frames,
+            decoded_ts: 0,
+            finished: false,
+        })
+    }
+
+    /// Seek to `secs` and return the track time decoding resumes at, which
+    /// is `secs` unless the container can only seek more coarsely
+    pub fn seek(&mut self, secs: f64) -> Option<f64> {
+        let seek_to = SeekTo::Time {
+            time: Time::from(secs),
+            track_id: Some(self.track_id),
+        };
+
+        match self.format.seek(SeekMode::Accurate, seek_to) {
+            Ok(seeked) => {
+                log::debug!("Seeked to {secs:.1}s (ts {})", seeked.actual_ts);
+                self.decoder.reset();
+                self.resampler.reset();
+                self.skip_until = seeked.required_ts;
+                self.decoded_ts = seeked.required_ts;
+                self.finished = false;
+
+                let resumed = match self.time_base {
+                    Some(time_base) => {
+                        let time = time_base.calc_time(seeked.required_ts);
+                        time.seconds as f64 + time.frac
+                    }
+                    None => secs,
+                };
+                Some(resumed)
+            }
+            Err(err) => {
+                log::error!("Seek error: {err}");
+                None
+            }
+        }
+    }
+
+    /// Track time decoding has reached, if the container has a time base
+    pub fn decoded_secs(&self) -> Option<f64> {
+        let time = self.time_base?.calc_time(self.decoded_ts);
+        Some(time.seconds as f64 + time.frac)
+    }
+
+    /// Seconds left to decode, if the container reports the track length
+    pub fn remaining_secs(&self) -> Option<f64> {
+        let time_base = self.time_base?;
+        let remaining = self.n_frames?.saturating_sub(self.decoded_ts);
+        let time = time_base.calc_time(remaining);
+
+        Some(time.seconds as f64 + time.frac)
+    }
+
+    /// Number of frames at `sample_rate` that `ts` time base units span.
+    /// Without a time base, timestamps are taken to count frames.
+    fn frames_in(&self, ts: u64, sample_rate: u32) -> usize {
+        match self.time_base {
+            Some(time_base) => {
+                let time = time_base.calc_time(ts);
+                ((time.seconds as f64 + time.frac) * sample_rate as f64).round() as usize
+            }
+            None => ts as usize,
+        }
+    }
+
+    /// Decode the next packet and append it to `out` in the output format.
+    /// Returns `false` once the track is exhausted.
+    pub fn decode_next(&mut self, out: &mut Vec<f32>) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(_) => {
+                    self.resampler.flush(out);
+                    self.finished = true;
+                    return false;
+                }
+            };
+
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            // Packets entirely before the seek target only prime the decoder
+            if packet.ts() + packet.dur() <= self.skip_until {
+                if let Err(err) = self.decoder.decode(&packet) {
+                    log::error!("Decode error: {err}");
+                }
+                continue;
+            }
+
+            let skip_ts = self.skip_until.saturating_sub(packet.ts());
+            self.decoded_ts = packet.ts() + packet.dur();
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(err) => {
+                    log::error!("Decode error: {err}");
+                    continue;
+                }
+            };
+
+            let spec = *decoded.spec();
+
+            // Some containers only learn the real layout from the first packet
+            if spec.channels.count() != self.channel_mapper.input_channels() {
+                self.channel_mapper = ChannelMapper::new(spec.channels, self.output.channels);
+            }
+
+            let mut samples = Vec::new();
+            if !interleave(decoded, &mut samples) {
+                continue;
+            }
+
+            // Trim the part of the first packet that lies before the seek target
+            let skip_frames = self.frames_in(skip_ts, spec.rate);
+            let skip_samples = (skip_frames * spec.channels.count()).min(samples.len());
+
+            let mut mapped = Vec::with_capacity(samples.len());
+            self.channel_mapper
+                .process(&samples[skip_samples..], &mut mapped);
+
+            self.resampler.process(&mapped, out);
+            return true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::player::output::{SinkFormat, write_wav};
+
+    const RATE: u32 = 8000;
+
+    /// A mono track whose samples count up, so each one tells its own frame
+    fn ramp(name: &str, frames: usize) -> Source {
+        let dir = std::env::temp_dir().join(format!("shelltrax-source-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+
+        let format = SinkFormat {
+            sample_rate: RATE,
+            channels: 1,
+        };
+        // Half a step up, so the conversion to 16 bits truncates to the frame
+        let samples: Vec<f32> = (0..frames)
+            .map(|i| (i as f32 + 0.5) / i16::MAX as f32)
+            .collect();
+        write_wav(&path, format, &samples);
+
+        let output = OutputFormat {
+            sample_rate: RATE,
+            channels: 1,
+            resample_quality: ResampleQuality::default(),
+        };
+        Source::open(&path, output, 1.0).unwrap()
+    }
+
+    /// Frame the next decoded sample comes from
+    fn next_frame(source: &mut Source) -> usize {
+        let mut out = Vec::new();
+        while out.is_empty() {
+            assert!(source.decode_next(&mut out), "track ended");
+        }
+        (out[0] * 32768.0).round() as usize
+    }
+
+    #[test]
+    fn plays_from_the_start() {
+        let mut source = ramp("start.wav", RATE as usize);
+        assert_eq!(next_frame(&mut source), 0);
+    }
+
+    #[test]
+    fn seek_resumes_at_the_reported_time() {
+        let mut source = ramp("seek.wav", 3 * RATE as usize);
+
+        // Neither lands on a packet boundary; the second goes back
+        for secs in [1.2345, 0.3071] {
+            let resumed = source.seek(secs).unwrap();
+            let frame = (resumed * RATE as f64).round() as usize;
+
+            assert!(
+                (resumed - secs).abs() <= 1.0 / RATE as f64,
+                "{resumed} for {secs}"
+            );
+            assert_eq!(next_frame(&mut source), frame, "seeking to {secs}");
+        }
+    }
+}
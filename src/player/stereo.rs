@@ -0,0 +1,242 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use super::dsp::{DspStage, StageKind};
+
+/// Time constant of the smoothing when any setting changes
+const RAMP_SECS: f32 = 0.01;
+
+/// Balance, mono downmix and left/right swap, shared between the player and
+/// the stream callback
+#[derive(Clone)]
+pub struct StereoControl {
+    /// -1.0 only left, 0.0 centre, 1.0 only right, stored as f32 bits
+    balance: Arc<AtomicU32>,
+    mono: Arc<AtomicBool>,
+    swap: Arc<AtomicBool>,
+}
+
+impl Default for StereoControl {
+    fn default() -> Self {
+        Self {
+            balance: Arc::new(AtomicU32::new(0.0f32.to_bits())),
+            mono: Arc::new(AtomicBool::new(false)),
+            swap: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl StereoControl {
+    pub fn balance(&self) -> f32 {
+        f32::from_bits(self.balance.load(Ordering::Relaxed))
+    }
+
+    pub fn set_balance(&self, balance: f32) {
+        self.balance
+            .store(balance.clamp(-1.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn is_mono(&self) -> bool {
+        self.mono.load(Ordering::Relaxed)
+    }
+
+    pub fn set_mono(&self, mono: bool) {
+        self.mono.store(mono, Ordering::Relaxed);
+    }
+
+    pub fn is_swapped(&self) -> bool {
+        self.swap.load(Ordering::Relaxed)
+    }
+
+    pub fn set_swapped(&self, swap: bool) {
+        self.swap.store(swap, Ordering::Relaxed);
+    }
+
+    /// How much of the incoming left and right ends up in each output
+    /// channel: `[[left from left, left from right], [right from left,
+    /// right from right]]`
+    fn target_matrix(&self) -> [[f32; 2]; 2] {
+        let mut matrix = if self.is_mono() {
+            [[0.5, 0.5], [0.5, 0.5]]
+        } else if self.is_swapped() {
+            [[0.0, 1.0], [1.0, 0.0]]
+        } else {
+            [[1.0, 0.0], [0.0, 1.0]]
+        };
+
+        // Turning towards one side only takes the other one down
+        let balance = self.balance();
+        let gains = [(1.0 - balance).min(1.0), (1.0 + balance).min(1.0)];
+
+        for (row, gain) in matrix.iter_mut().zip(gains) {
+            row.iter_mut().for_each(|weight| *weight *= gain);
+        }
+
+        matrix
+    }
+}
+
+/// Applies the `StereoControl` settings to the first two channels, easing
+/// into every change so toggling does not click. Mono output is left alone.
+pub struct StereoImage {
+    control: StereoControl,
+    channels: usize,
+    matrix: [[f32; 2]; 2],
+
+    /// One-pole smoothing coefficient per frame
+    coeff: f32,
+}
+
+impl StereoImage {
+    pub fn new(control: StereoControl, sample_rate: u32, channels: usize) -> Self {
+        let matrix = control.target_matrix();
+
+        Self {
+            control,
+            channels: channels.max(1),
+            matrix,
+            coeff: 1.0 - (-1.0 / (RAMP_SECS * sample_rate as f32)).exp(),
+        }
+    }
+}
+
+impl DspStage for StereoImage {
+    fn kind(&self) -> StageKind {
+        StageKind::Stereo
+    }
+
+    fn process(&mut self, frames: &mut [f32]) {
+        if self.channels < 2 {
+            return;
+        }
+
+        let target = self.control.target_matrix();
+        if self.matrix == target && target == [[1.0, 0.0], [0.0, 1.0]] {
+            return;
+        }
+
+        for frame in frames.chunks_mut(self.channels) {
+            if self.matrix != target {
+                for (row, target_row) in self.matrix.iter_mut().zip(target) {
+                    for (weight, target) in row.iter_mut().zip(target_row) {
+                        *weight += (target - *weight) * self.coeff;
+
+                        // Snap once the difference is inaudible
+                        if (target - *weight).abs() < 1e-4 {
+                            *weight = target;
+                        }
+                    }
+                }
+            }
+
+            let (left, right) = (frame[0], frame[1]);
+            let [to_left, to_right] = self.matrix;
+
+            frame[0] = left * to_left[0] + right * to_left[1];
+            frame[1] = left * to_right[0] + right * to_right[1];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 1000;
+
+    /// One stereo frame of (0.2, 0.6) after the stage has settled
+    fn settled(control: &StereoControl) -> (f32, f32) {
+        let mut stage = StereoImage::new(control.clone(), RATE, 2);
+        let mut frame = [0.2, 0.6];
+        stage.process(&mut frame);
+        (frame[0], frame[1])
+    }
+
+    fn assert_frame(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-6 && (actual.1 - expected.1).abs() < 1e-6,
+            "{actual:?}, expected {expected:?}"
+        );
+    }
+
+    #[test]
+    fn centred_passes_through() {
+        assert_frame(settled(&StereoControl::default()), (0.2, 0.6));
+    }
+
+    #[test]
+    fn full_balance_silences_the_other_side() {
+        let control = StereoControl::default();
+
+        control.set_balance(-1.0);
+        assert_frame(settled(&control), (0.2, 0.0));
+
+        control.set_balance(1.0);
+        assert_frame(settled(&control), (0.0, 0.6));
+
+        // Half way only turns the far side down
+        control.set_balance(0.5);
+        assert_frame(settled(&control), (0.1, 0.6));
+    }
+
+    #[test]
+    fn mono_averages_both_sides() {
+        let control = StereoControl::default();
+        control.set_mono(true);
+
+        assert_frame(settled(&control), (0.4, 0.4));
+    }
+
+    #[test]
+    fn swap_exchanges_the_sides() {
+        let control = StereoControl::default();
+        control.set_swapped(true);
+
+        assert_frame(settled(&control), (0.6, 0.2));
+    }
+
+    #[test]
+    fn mono_wins_over_swap() {
+        let control = StereoControl::default();
+        control.set_swapped(true);
+        control.set_mono(true);
+
+        assert_eq!(control.target_matrix(), [[0.5, 0.5], [0.5, 0.5]]);
+        assert_frame(settled(&control), (0.4, 0.4));
+    }
+
+    #[test]
+    fn changes_ease_in_and_then_snap() {
+        let control = StereoControl::default();
+        let mut stage = StereoImage::new(control.clone(), RATE, 2);
+
+        control.set_swapped(true);
+        let target = control.target_matrix();
+
+        // Partly there after one frame
+        stage.process(&mut [0.0; 2]);
+        assert!(stage.matrix != target);
+        assert!(stage.matrix[0][1] > 0.0 && stage.matrix[0][1] < 0.5);
+
+        // 10 ms time constant: exactly on target well within 200 ms
+        stage.process(&mut [0.0; 2 * 200]);
+        assert_eq!(stage.matrix, target);
+
+        let mut frame = [0.2, 0.6];
+        stage.process(&mut frame);
+        assert_eq!(frame, [0.6, 0.2]);
+    }
+
+    #[test]
+    fn mono_output_is_untouched() {
+        let control = StereoControl::default();
+        control.set_balance(-1.0);
+        let mut stage = StereoImage::new(control, RATE, 1);
+
+        let mut data = [0.5; 4];
+        stage.process(&mut data);
+        assert_eq!(data, [0.5; 4]);
+    }
+}
@@ -0,0 +1,344 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU32, Ordering},
+};
+
+/// Slowest and fastest playback speed
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+
+/// Output hop, half the window, in seconds
+const HOP_SECS: f32 = 0.02;
+
+/// How far the analysis window may slide to line up with the previous one
+const TOLERANCE_SECS: f32 = 0.008;
+
+/// Only every this many frames is compared when lining windows up
+const COMPARE_STRIDE: usize = 4;
+
+/// Playback speed shared between the UI and the decode thread
+#[derive(Clone)]
+pub struct SpeedControl(Arc<AtomicU32>);
+
+impl Default for SpeedControl {
+    fn default() -> Self {
+        Self(Arc::new(AtomicU32::new(1.0f32.to_bits())))
+    }
+}
+
+impl SpeedControl {
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, speed: f32) {
+        self.0.store(
+            speed.clamp(MIN_SPEED, MAX_SPEED).to_bits(),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+/// Changes tempo without changing pitch (WSOLA).
+///
+/// Hann windows are cut from the input every `hop * speed` frames and
+/// overlap-added every `hop` frames. Each window is slid by up to
+/// `tolerance` frames to where it best continues the previous one, which
+/// keeps waveforms in phase and avoids the warble of plain overlap-add.
+/// At 1x the input passes straight through.
+pub struct TimeStretch {
+    channels: usize,
+    speed: f32,
+
+    hop: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+
+    /// Interleaved input not yet consumed
+    input: Vec<f32>,
+
+    /// Nominal start of the next analysis window, in frames into `input`
+    next: f64,
+
+    /// Where the previous window would have carried on, in frames into
+    /// `input`, and the second half of that window, already weighted
+    natural: Option<usize>,
+    tail: Vec<f32>,
+
+    /// Frames taken in and given out since the last reset, so `finish`
+    /// knows how much of its flush is real audio
+    consumed: u64,
+    produced: u64,
+}
+
+impl TimeStretch {
+    pub fn new(sample_rate: u32, channels: usize, speed: f32) -> Self {
+        let channels = channels.max(1);
+        let hop = ((sample_rate as f32 * HOP_SECS) as usize).max(16);
+        let tolerance = (sample_rate as f32 * TOLERANCE_SECS) as usize;
+
+        // Periodic Hann, which sums to one at 50% overlap
+        let size = hop * 2;
+        let window = (0..size)
+            .map(|i| {
+                let phase = std::f32::consts::PI * i as f32 / size as f32;
+                phase.sin().powi(2)
+            })
+            .collect();
+
+        Self {
+            channels,
+            speed: speed.clamp(MIN_SPEED, MAX_SPEED),
+            hop,
+            tolerance,
+            window,
+            input: Vec::new(),
+            next: 0.0,
+            natural: None,
+            tail: vec![0.0; hop * channels],
+            consumed: 0,
+            produced: 0,
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Change speed, dropping whatever is buffered
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.reset();
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.speed == 1.0
+    }
+
+    /// Output frames still held back from what has been taken in, at the
+    /// current speed. Whatever goes in next comes out this much later.
+    pub fn latency_frames(&self) -> usize {
+        if self.is_bypassed() {
+            return 0;
+        }
+
+        let expected = (self.consumed as f64 / self.speed as f64).round() as u64;
+        expected.saturating_sub(self.produced) as usize
+    }
+
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.next = 0.0;
+        self.natural = None;
+        self.tail.fill(0.0);
+        self.consumed = 0;
+        self.produced = 0;
+    }
+
+    /// Stretch `samples` in place. Output lags the input by about a window.
+    pub fn process(&mut self, samples: &mut Vec<f32>) {
+        if self.is_bypassed() {
+            return;
+        }
+
+        let ch = self.channels;
+        let window_len = self.hop * 2;
+
+        self.consumed += (samples.len() / ch) as u64;
+        self.input.extend_from_slice(samples);
+        samples.clear();
+
+        loop {
+            let nominal = self.next as usize;
+            let frames = self.input.len() / ch;
+            if nominal + self.tolerance + window_len > frames {
+                break;
+            }
+
+            let start = match self.natural {
+                Some(natural) => self.best_start(nominal, natural),
+                None => nominal,
+            };
+
+            // First half overlaps the previous window's tail
+            for i in 0..self.hop {
+                let weight = self.window[i];
+                for c in 0..ch {
+                    let x = self.input[(start + i) * ch + c];
+                    samples.push(self.tail[i * ch + c] + x * weight);
+                }
+            }
+
+            for i in 0..self.hop {
+                let weight = self.window[self.hop + i];
+                for c in 0..ch {
+                    self.tail[i * ch + c] = self.input[(start + self.hop + i) * ch + c] * weight;
+                }
+            }
+
+            self.natural = Some(start + self.hop);
+            self.next += self.hop as f64 * self.speed as f64;
+
+            // Drop input no later window can reach
+            let keep_from = (self.next as usize)
+                .saturating_sub(self.tolerance)
+                .min(start + self.hop);
+            if keep_from > 0 {
+                self.input.drain(..keep_from * ch);
+                self.next -= keep_from as f64;
+                self.natural = self.natural.map(|n| n - keep_from);
+            }
+        }
+
+        self.produced += (samples.len() / ch) as u64;
+    }
+
+    /// Push out everything still buffered, e.g. at the end of a track, so
+    /// the next one starts from a clean state. Only the stretched length of
+    /// the real input comes out, so no silence is left between tracks.
+    pub fn finish(&mut self, samples: &mut Vec<f32>) {
+        if self.is_bypassed() {
+            return;
+        }
+
+        let ch = self.channels;
+        let consumed = self.consumed + (samples.len() / ch) as u64;
+        let expected = (consumed as f64 / self.speed as f64).round() as u64;
+        let produced = self.produced;
+
+        // Enough silence to carry every real frame through a window; what
+        // comes out of it past `expected` is cut off again
+        let pad = (self.tolerance + self.hop * 2) * ch;
+        let mut padded = std::mem::take(samples);
+        padded.resize(padded.len() + pad, 0.0);
+
+        self.process(&mut padded);
+        padded.extend_from_slice(&self.tail);
+        padded.truncate(expected.saturating_sub(produced) as usize * ch);
+
+        *samples = padded;
+        self.reset();
+    }
+
+    /// Start within `nominal ± tolerance` whose first half best matches the
+    /// frames that naturally followed the previous window
+    fn best_start(&self, nominal: usize, natural: usize) -> usize {
+        let ch = self.channels;
+        let lowest = nominal.saturating_sub(self.tolerance);
+
+        let mut best = nominal;
+        let mut best_score = f32::MIN;
+
+        for start in lowest..=nominal + self.tolerance {
+            let mut score = 0.0;
+            for i in (0..self.hop).step_by(COMPARE_STRIDE) {
+                let a = (start + i) * ch;
+                let b = (natural + i) * ch;
+                for c in 0..ch {
+                    score += self.input[a + c] * self.input[b + c];
+                }
+            }
+
+            if score > best_score {
+                best_score = score;
+                best = start;
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stretch `frames` of a stereo tone in blocks, then flush
+    fn stretch(speed: f32, frames: usize) -> Vec<f32> {
+        let mut stretch = TimeStretch::new(44100, 2, speed);
+        let input: Vec<f32> = (0..frames)
+            .flat_map(|i| {
+                let x = (i as f32 * 0.05).sin() * 0.5;
+                [x, x]
+            })
+            .collect();
+
+        let mut output = Vec::new();
+        for block in input.chunks(1024 * 2) {
+            let mut samples = block.to_vec();
+            stretch.process(&mut samples);
+            output.extend(samples);
+        }
+
+        let mut samples = Vec::new();
+        stretch.finish(&mut samples);
+        output.extend(samples);
+        output
+    }
+
+    #[test]
+    fn finish_emits_stretched_length_only() {
+        for speed in [0.5, 0.75, 1.5, 2.0, 3.0] {
+            let frames = 44100;
+            let output = stretch(speed, frames);
+
+            let expected = (frames as f32 / speed).round() as usize;
+            assert_eq!(output.len(), expected * 2, "speed {speed}");
+        }
+    }
+
+    #[test]
+    fn finish_leaves_no_silent_tail() {
+        let output = stretch(1.5, 44100);
+
+        // The last 10 ms still carry the tone rather than padding
+        let last = &output[output.len() - 441 * 2..];
+        let peak = last.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+        assert!(peak > 0.1, "peak {peak}");
+    }
+
+    #[test]
+    fn bypass_at_normal_speed() {
+        let output = stretch(1.0, 4096);
+        assert_eq!(output.len(), 4096 * 2);
+    }
+
+    #[test]
+    fn latency_is_what_finish_still_gives_out() {
+        for speed in [0.5, 1.0, 1.5, 3.0] {
+            let mut stretch = TimeStretch::new(44100, 2, speed);
+
+            let mut samples = vec![0.25; 5000 * 2];
+            stretch.process(&mut samples);
+            let latency = stretch.latency_frames();
+
+            let mut rest = Vec::new();
+            stretch.finish(&mut rest);
+
+            assert_eq!(rest.len(), latency * 2, "speed {speed}");
+            assert_eq!(stretch.latency_frames(), 0);
+        }
+
+        // About a window at normal speeds
+        let mut stretch = TimeStretch::new(44100, 1, 1.5);
+        stretch.process(&mut vec![0.25; 44100]);
+        let ms = stretch.latency_frames() as f32 / 44.1;
+        assert!((20.0..=80.0).contains(&ms), "{ms} ms");
+    }
+
+    #[test]
+    fn reset_starts_counting_again() {
+        let mut stretch = TimeStretch::new(44100, 1, 2.0);
+
+        let mut samples = vec![0.25; 10_000];
+        stretch.process(&mut samples);
+        stretch.reset();
+
+        let mut samples = vec![0.25; 4000];
+        stretch.process(&mut samples);
+        let mut rest = Vec::new();
+        stretch.finish(&mut rest);
+
+        assert_eq!(samples.len() + rest.len(), 2000);
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+/// Mono samples kept for readers, a power of two
+const TAP_LEN: usize = 8192;
+
+/// The most recent audio leaving the player, mixed down to mono, for
+/// visualizers and meters. The stream callback overwrites it without
+/// locking; a reader may see a few samples of a newer block mixed in,
+/// which does not matter for display.
+#[derive(Clone)]
+pub struct OutputTap {
+    inner: Arc<TapInner>,
+}
+
+struct TapInner {
+    samples: Box<[AtomicU32]>,
+
+    /// Mono samples written since startup; the next one goes at
+    /// `written % TAP_LEN`
+    written: AtomicUsize,
+    sample_rate: AtomicU32,
+}
+
+impl Default for OutputTap {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(TapInner {
+                samples: (0..TAP_LEN).map(|_| AtomicU32::new(0)).collect(),
+                written: AtomicUsize::new(0),
+                sample_rate: AtomicU32::new(44100),
+            }),
+        }
+    }
+}
+
+impl OutputTap {
+    pub fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate.load(Ordering::Relaxed)
+    }
+
+    pub fn set_sample_rate(&self, rate: u32) {
+        self.inner.sample_rate.store(rate, Ordering::Relaxed);
+    }
+
+    /// Mono samples written since startup. Unchanged between two reads
+    /// means nothing is playing.
+    pub fn written(&self) -> usize {
+        self.inner.written.load(Ordering::Acquire)
+    }
+
+    /// Append interleaved `data`, averaging the channels
+    pub fn write(&self, data: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        let mut at = self.inner.written.load(Ordering::Relaxed);
+
+        for frame in data.chunks_exact(channels) {
+            let mono = frame.iter().sum::<f32>() / channels as f32;
+            self.inner.samples[at % TAP_LEN].store(mono.to_bits(), Ordering::Relaxed);
+            at = at.wrapping_add(1);
+        }
+
+        self.inner.written.store(at, Ordering::Release);
+    }
+
+    /// Fill `out` with the latest samples, oldest first. Only the first
+    /// 8192 are filled in.
+    pub fn latest(&self, out: &mut [f32]) {
+        let len = out.len().min(TAP_LEN);
+        let end = self.written();
+        let start = end.wrapping_sub(len);
+
+        for (i, sample) in out[..len].iter_mut().enumerate() {
+            let at = start.wrapping_add(i) % TAP_LEN;
+            *sample = f32::from_bits(self.inner.samples[at].load(Ordering::Relaxed));
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use super::dsp::{DspStage, StageKind};
+
+/// Time constant of the gain smoothing in the stream callback
+const RAMP_SECS: f32 = 0.01;
+
+/// Volume and mute state shared between the player and the stream callback
+#[derive(Clone)]
+pub struct VolumeControl {
+    percent: Arc<AtomicU32>,
+    muted: Arc<AtomicBool>,
+}
+
+impl VolumeControl {
+    pub fn new(percent: u8) -> Self {
+        Self {
+            percent: Arc::new(AtomicU32::new(percent.min(100) as u32)),
+            muted: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn percent(&self) -> u8 {
+        self.percent.load(Ordering::Relaxed) as u8
+    }
+
+    pub fn set_percent(&self, percent: u8) {
+        self.percent
+            .store(percent.min(100) as u32, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Linear gain the output should be heading towards. The percentage is
+    /// squared so the steps sound roughly even.
+    fn target_gain(&self) -> f32 {
+        if self.is_muted() {
+            return 0.0;
+        }
+
+        let level = self.percent() as f32 / 100.0;
+        level * level
+    }
+}
+
+/// Applies the volume in the stream callback, easing into changes instead of
+/// jumping so that adjustments do not produce zipper noise
+pub struct VolumeRamp {
+    control: VolumeControl,
+    channels: usize,
+    gain: f32,
+
+    /// One-pole smoothing coefficient per frame
+    coeff: f32,
+}
+
+impl VolumeRamp {
+    pub fn new(control: VolumeControl, sample_rate: u32, channels: usize) -> Self {
+        let gain = control.target_gain();
+
+        Self {
+            control,
+            channels: channels.max(1),
+            gain,
+            coeff: 1.0 - (-1.0 / (RAMP_SECS * sample_rate as f32)).exp(),
+        }
+    }
+}
+
+impl DspStage for VolumeRamp {
+    fn kind(&self) -> StageKind {
+        StageKind::Volume
+    }
+
+    fn process(&mut self, data: &mut [f32]) {
+        let target = self.control.target_gain();
+
+        if self.gain == target {
+            if target != 1.0 {
+                data.iter_mut().for_each(|sample| *sample *= target);
+            }
+            return;
+        }
+
+        for frame in data.chunks_mut(self.channels) {
+            self.gain += (target - self.gain) * self.coeff;
+
+            // Snap once the difference is inaudible so the fast path kicks in
+            if (target - self.gain).abs() < 1e-4 {
+                self.gain = target;
+            }
+
+            frame.iter_mut().for_each(|sample| *sample *= self.gain);
+        }
+    }
+}
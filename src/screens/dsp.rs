@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let latencies = app.player.state().dsp_latencies;
+    let latency_of = |kind| {
+        latencies
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, latency)| *latency)
+            .unwrap_or_default()
+    };
+
+    let total: Duration = app
+        .dsp
+        .entries
+        .iter()
+        .filter(|slot| slot.enabled)
+        .map(|slot| latency_of(slot.kind))
+        .sum();
+
+    let items: Vec<ListItem> = app
+        .dsp
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(i, slot)| {
+            let check = if slot.enabled { "[x]" } else { "[ ]" };
+            let latency = latency_of(slot.kind).as_secs_f64() * 1000.0;
+
+            let item = ListItem::new(format!(
+                "{check} {}. {:<12} {latency:4.1} ms",
+                i + 1,
+                slot.kind.label()
+            ));
+
+            if slot.enabled {
+                item
+            } else {
+                item.style(Style::default().fg(Color::DarkGray))
+            }
+        })
+        .collect();
+
+    let title = format!(
+        "DSP chain (latency {:.1} ms)",
+        total.as_secs_f64() * 1000.0
+    );
+
+    let list = List::new(items)
+        .block(Block::default().title(title).borders(Borders::ALL))
+        .highlight_style(Style::default().bg(Color::Cyan).fg(Color::Black))
+        .highlight_symbol("➤ ");
+
+    frame.render_stateful_widget(list, area, &mut app.dsp.state);
+}
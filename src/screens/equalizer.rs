@@ -0,0 +1,98 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+use crate::equalizer::EqualizerFocus;
+use crate::player::{EQ_BANDS, MAX_BAND_DB};
+
+/// Width of the slider drawn for each band, in cells
+const SLIDER_WIDTH: usize = 25;
+
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let eq = &mut app.equalizer;
+
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(area);
+
+    // ───── Left: Bands ─────
+    let band_items: Vec<ListItem> = EQ_BANDS
+        .iter()
+        .zip(eq.gains)
+        .map(|(freq, gain)| {
+            let label = if *freq >= 1000.0 {
+                format!("{}k", freq / 1000.0)
+            } else {
+                format!("{freq}")
+            };
+
+            ListItem::new(format!("{label:>5} Hz  {}  {gain:+5.1} dB", slider(gain)))
+        })
+        .collect();
+
+    let mut band_state = ListState::default();
+    band_state.select(Some(eq.band));
+
+    let band_style = if eq.focus == EqualizerFocus::Bands {
+        Style::default().bg(Color::Magenta).fg(Color::Black)
+    } else {
+        Style::default().add_modifier(Modifier::BOLD)
+    };
+
+    let bands = List::new(band_items)
+        .block(Block::default().title("Equalizer").borders(Borders::ALL))
+        .highlight_symbol("➤ ")
+        .highlight_style(band_style);
+
+    frame.render_stateful_widget(bands, chunks[0], &mut band_state);
+
+    // ───── Right: Presets ─────
+    let preset_items: Vec<ListItem> = eq
+        .presets
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(i, preset)| {
+            let marker = if eq.is_user_preset(i) { "*" } else { " " };
+            ListItem::new(format!("{marker} {}", preset.name))
+        })
+        .collect();
+
+    let presets = List::new(preset_items)
+        .block(Block::default().title("Presets").borders(Borders::ALL))
+        .highlight_symbol("➤ ")
+        .highlight_style(Style::default().bg(Color::Magenta).fg(Color::Black));
+
+    if eq.focus == EqualizerFocus::Presets {
+        frame.render_stateful_widget(presets, chunks[1], &mut eq.presets.state);
+    } else {
+        frame.render_widget(presets, chunks[1]);
+    }
+}
+
+/// Horizontal slider centred on 0 dB, e.g. `[        |====    ]`
+fn slider(gain: f32) -> String {
+    let centre = SLIDER_WIDTH / 2;
+    let offset = (gain / MAX_BAND_DB * centre as f32).round() as isize;
+    let target = (centre as isize + offset).clamp(0, SLIDER_WIDTH as isize - 1) as usize;
+
+    let (low, high) = if target < centre {
+        (target, centre)
+    } else {
+        (centre, target)
+    };
+
+    let cells: String = (0..SLIDER_WIDTH)
+        .map(|i| {
+            if i == centre {
+                '|'
+            } else if i >= low && i <= high {
+                '='
+            } else {
+                ' '
+            }
+        })
+        .collect();
+
+    format!("[{cells}]")
+}
@@ -0,0 +1,66 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+use crate::lyrics::Lyrics;
+
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let lyrics = app.current_track.as_ref().and_then(|t| t.lyrics.as_ref());
+
+    let title = match lyrics {
+        Some(Lyrics::Synced(_)) => "Lyrics (synced)",
+        _ => "Lyrics",
+    };
+    let block = Block::default().title(title).borders(Borders::ALL);
+
+    let paragraph = match lyrics {
+        Some(lyrics @ Lyrics::Synced(_)) => {
+            let current = lyrics.current_line(app.elapsed());
+
+            // Sung lines dimmed, the current one highlighted
+            let lines: Vec<Line> = lyrics
+                .lines()
+                .into_iter()
+                .enumerate()
+                .map(|(i, text)| {
+                    let style = match current {
+                        Some(current) if i == current => Style::default()
+                            .fg(Color::Yellow)
+                            .add_modifier(Modifier::BOLD),
+                        Some(current) if i < current => Style::default().fg(Color::DarkGray),
+                        _ => Style::default(),
+                    };
+                    Line::styled(text, style)
+                })
+                .collect();
+
+            // Keep the current line in the middle; lines are not wrapped so
+            // that each takes exactly one row
+            let middle = block.inner(area).height as usize / 2;
+            let scroll = current.unwrap_or(0).saturating_sub(middle);
+
+            Paragraph::new(lines)
+                .alignment(Alignment::Center)
+                .scroll((scroll as u16, 0))
+        }
+        Some(Lyrics::Unsynced(lines)) => {
+            let lines: Vec<Line> = lines.iter().map(|text| Line::raw(text.as_str())).collect();
+
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: true })
+                .scroll((app.lyrics_scroll as u16, 0))
+        }
+        None => {
+            let message = if app.current_track.is_some() {
+                "No lyrics for this track"
+            } else {
+                "Nothing playing"
+            };
+
+            Paragraph::new(message)
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(Color::DarkGray))
+        }
+    };
+
+    frame.render_widget(paragraph.block(block), area);
+}
@@ -1,2 +1,7 @@
 pub mod browser;
+pub mod dsp;
+pub mod equalizer;
 pub mod library;
+pub mod lyrics;
+pub mod output;
+pub mod visualizer;
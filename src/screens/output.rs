@@ -0,0 +1,42 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let current = app.settings.output_device.as_ref();
+    let underruns = app.player.state().underruns;
+
+    let items: Vec<ListItem> = app
+        .devices
+        .entries
+        .iter()
+        .map(|entry| {
+            let selection = entry.as_ref().map(|d| &d.selection);
+            let marker = if selection == current { "*" } else { " " };
+
+            let label = match entry {
+                None => "System default".to_string(),
+                Some(device) if device.is_default => {
+                    format!(
+                        "{}: {} (default)",
+                        device.selection.host, device.selection.name
+                    )
+                }
+                Some(device) => format!("{}: {}", device.selection.host, device.selection.name),
+            };
+
+            ListItem::new(format!("{marker} {label}"))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title(format!("Output (underruns: {underruns})"))
+                .borders(Borders::ALL),
+        )
+        .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black))
+        .highlight_symbol("➤ ");
+
+    frame.render_stateful_widget(list, area, &mut app.devices.state);
+}
@@ -0,0 +1,123 @@
+use ratatui::{prelude::*, widgets::*};
+
+use crate::app::App;
+
+/// Partial blocks from empty to full, in eighths of a cell
+const BLOCKS: [&str; 9] = [" ", "▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+
+/// Frequencies labelled under the bars
+const LOG_LABELS: [(f32, &str); 8] = [
+    (50.0, "50"),
+    (100.0, "100"),
+    (200.0, "200"),
+    (500.0, "500"),
+    (1000.0, "1k"),
+    (2000.0, "2k"),
+    (5000.0, "5k"),
+    (10000.0, "10k"),
+];
+const LINEAR_LABELS: [(f32, &str); 8] = [
+    (0.0, "0"),
+    (2000.0, "2k"),
+    (4000.0, "4k"),
+    (6000.0, "6k"),
+    (8000.0, "8k"),
+    (10000.0, "10k"),
+    (12000.0, "12k"),
+    (14000.0, "14k"),
+];
+
+pub fn draw(frame: &mut Frame, app: &mut App, area: Rect) {
+    let settings = app.settings.visualizer;
+    let spectrum = &app.spectrum;
+
+    let title = format!(
+        "Spectrum ({} bars, {}{})",
+        spectrum.bars.len(),
+        if settings.log_scale { "log" } else { "linear" },
+        if settings.peak_hold { ", peak hold" } else { "" },
+    );
+
+    let block = Block::default().title(title).borders(Borders::ALL);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let count = spectrum.bars.len();
+    if count == 0 || inner.width == 0 || inner.height < 2 {
+        return;
+    }
+
+    // Bars above, frequency labels on the last row
+    let height = inner.height - 1;
+    let bar_width = (inner.width as usize / count).max(1);
+    let gap = if bar_width >= 3 { 1 } else { 0 };
+    let total = (bar_width * count).min(inner.width as usize) as u16;
+    let left = inner.left() + (inner.width - total) / 2;
+
+    let buf = frame.buffer_mut();
+    let bars = spectrum.bars.iter().zip(spectrum.peaks());
+
+    for (i, (&level, peak)) in bars.enumerate() {
+        let x0 = left + (i * bar_width) as u16;
+        if x0 >= left + total {
+            break;
+        }
+
+        let eighths = (level * height as f32 * 8.0).round() as usize;
+        let peak_row = (peak * height as f32) as usize;
+
+        for row in 0..height as usize {
+            let y = inner.bottom() - 2 - row as u16;
+            let fill = eighths.saturating_sub(row * 8).min(8);
+
+            let (symbol, colour) = if fill > 0 {
+                (BLOCKS[fill], level_colour(row, height as usize))
+            } else if settings.peak_hold && row == peak_row && peak > 0.0 {
+                ("▔", Color::White)
+            } else {
+                continue;
+            };
+
+            for x in x0..(x0 + (bar_width - gap) as u16).min(left + total) {
+                buf[(x, y)].set_symbol(symbol).set_fg(colour);
+            }
+        }
+    }
+
+    let labels: &[(f32, &str)] = if settings.log_scale {
+        &LOG_LABELS
+    } else {
+        &LINEAR_LABELS
+    };
+
+    // Skip labels that would run into the previous one
+    let y = inner.bottom() - 1;
+    let mut free_from = inner.left();
+    for (freq, label) in labels {
+        let Some(position) = spectrum.position_of(*freq, settings.log_scale) else {
+            continue;
+        };
+
+        let x = left + (position * total as f32) as u16;
+        let width = label.len() as u16;
+        if x < free_from || x + width > inner.right() {
+            continue;
+        }
+
+        buf.set_string(x, y, label, Style::default().fg(Color::DarkGray));
+        free_from = x + width + 1;
+    }
+}
+
+/// Green at the bottom, through yellow, to red near the top
+fn level_colour(row: usize, height: usize) -> Color {
+    let fraction = row as f32 / height as f32;
+
+    if fraction >= 0.85 {
+        Color::Red
+    } else if fraction >= 0.6 {
+        Color::Yellow
+    } else {
+        Color::Green
+    }
+}
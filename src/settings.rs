@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+use crate::player::{
+    DeviceSelection, FadeCurve, ReplayGainSettings, ResampleQuality, SinkConfig, StageSlot,
+    default_slots,
+};
+use crate::visualizer::VisualizerSettings;
+
+/// User preferences stored in `settings.json` next to `library.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Software volume in percent
+    pub volume: u8,
+    pub muted: bool,
+
+    /// -1.0 only left, 0.0 centre, 1.0 only right
+    pub balance: f32,
+
+    /// Play both channels mixed down to mono, or with left and right swapped
+    pub mono: bool,
+    pub swap_channels: bool,
+
+    /// Interpolation used when the output device runs at a different rate than the file
+    pub resample_quality: ResampleQuality,
+
+    /// Overlap between tracks in autoplay, 0 to play them back to back
+    pub crossfade_secs: f32,
+    pub crossfade_curve: FadeCurve,
+
+    /// Fade on pause, resume, stop and track changes in milliseconds, 0 to
+    /// cut instantly
+    pub fade_ms: u32,
+
+    /// Skip the crossfade between consecutive tracks of the same album
+    pub album_aware_crossfade: bool,
+
+    pub replaygain: ReplayGainSettings,
+
+    /// Let the loudness scan write its results into MP3 and FLAC files as
+    /// ReplayGain tags
+    pub write_replaygain_tags: bool,
+
+    /// Tempo, 0.5 to 3.0, with the pitch kept
+    pub playback_speed: f32,
+
+    /// Graphic equalizer band gains in dB
+    pub eq_gains: [f32; 10],
+
+    /// DSP stages in processing order, with their on/off state
+    pub dsp_chain: Vec<StageSlot>,
+
+    /// Where audio goes: the sound card, nowhere, or a WAV file
+    pub sink: SinkConfig,
+
+    /// Sound card picked on the output screen, `None` for the system default
+    pub output_device: Option<DeviceSelection>,
+
+    /// Bar count, frequency scale and peak hold of the spectrum screen
+    pub visualizer: VisualizerSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            volume: 100,
+            muted: false,
+            balance: 0.0,
+            mono: false,
+            swap_channels: false,
+            resample_quality: ResampleQuality::default(),
+            crossfade_secs: 0.0,
+            crossfade_curve: FadeCurve::default(),
+            fade_ms: 30,
+            album_aware_crossfade: false,
+            replaygain: ReplayGainSettings::default(),
+            write_replaygain_tags: false,
+            playback_speed: 1.0,
+            eq_gains: [0.0; 10],
+            dsp_chain: default_slots(),
+            sink: SinkConfig::default(),
+            output_device: None,
+            visualizer: VisualizerSettings::default(),
+        }
+    }
+}
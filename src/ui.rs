@@ -1,5 +1,8 @@
+use std::time::Duration;
+
 use crate::{
     app::{App, AppScreen},
+    meters::{FLOOR_DB, MeterChannel},
     screens,
 };
 use ratatui::prelude::*;
@@ -8,30 +11,45 @@ use ratatui::widgets::{
     Paragraph,
 };
 
-use std::time::Instant;
-
 pub fn draw_ui(frame: &mut Frame, app: &mut App) {
+    let status_height = if app.status.is_some() { 1 } else { 0 };
+
     let layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Min(1),    // Main screen
-            Constraint::Length(2), // Footer
+            Constraint::Min(1),                // Main screen
+            Constraint::Length(status_height), // Status message
+            Constraint::Length(2),             // Footer
         ])
         .split(frame.area());
 
     match app.screen {
         AppScreen::Library => screens::library::draw(frame, app, layout[0]),
         AppScreen::Browser => screens::browser::draw(frame, app, layout[0]),
-        _ => println!("ok"),
+        AppScreen::Equalizer => screens::equalizer::draw(frame, app, layout[0]),
+        AppScreen::Dsp => screens::dsp::draw(frame, app, layout[0]),
+        AppScreen::Output => screens::output::draw(frame, app, layout[0]),
+        AppScreen::Visualizer => screens::visualizer::draw(frame, app, layout[0]),
+        AppScreen::Lyrics => screens::lyrics::draw(frame, app, layout[0]),
+    }
+
+    if let Some(status) = &app.status {
+        let line = Paragraph::new(status.as_str()).style(Style::default().fg(Color::Yellow));
+        frame.render_widget(line, layout[1]);
     }
 
-    render_footer(frame, app, layout[1]);
+    render_footer(frame, app, layout[2]);
 }
 
 pub fn highlight_style(screen: AppScreen) -> Style {
     match screen {
         AppScreen::Library => Style::default().bg(Color::Green).fg(Color::Black),
         AppScreen::Browser => Style::default().bg(Color::Blue).fg(Color::White),
+        AppScreen::Equalizer => Style::default().bg(Color::Magenta).fg(Color::Black),
+        AppScreen::Dsp => Style::default().bg(Color::Cyan).fg(Color::Black),
+        AppScreen::Output => Style::default().bg(Color::Yellow).fg(Color::Black),
+        AppScreen::Visualizer => Style::default().bg(Color::Red).fg(Color::Black),
+        AppScreen::Lyrics => Style::default().bg(Color::White).fg(Color::Black),
     }
 }
 
@@ -41,20 +59,10 @@ fn render_footer(f: &mut Frame, app: &App, area: Rect) {
             "Rendering footer: {} – {}, elapsed: {:?}",
             track.album_artist,
             track.title,
-            app.playback_start.map(|t| t.elapsed())
+            app.elapsed()
         );
 
-        let elapsed = app.playback_start.map(|start| {
-            let now = if let Some(paused_at) = app.paused_at {
-                paused_at // freeze at time of pause
-            } else {
-                Instant::now()
-            };
-
-            now.duration_since(start)
-                .saturating_sub(app.paused_duration)
-                .as_secs()
-        }).unwrap_or(0);
+        let elapsed = app.elapsed().as_secs();
 
         let dur = track.duration.unwrap_or(0);
         let pos = elapsed.min(dur);
@@ -65,23 +73,64 @@ fn render_footer(f: &mut Frame, app: &App, area: Rect) {
             0.0
         };
 
+        let mut volume = if app.settings.muted {
+            "muted".to_string()
+        } else {
+            format!("vol {}%", app.settings.volume)
+        };
+
+        // Only mention the stereo image when it differs from plain stereo
+        let balance = app.settings.balance;
+        if balance < 0.0 {
+            volume += &format!(" bal L{:.0}", -balance * 100.0);
+        } else if balance > 0.0 {
+            volume += &format!(" bal R{:.0}", balance * 100.0);
+        }
+        if app.settings.mono {
+            volume += " mono";
+        } else if app.settings.swap_channels {
+            volume += " swap";
+        }
+
+        // Times are in track time; off 1x also show how long is left in real time
+        let speed = app.settings.playback_speed;
+        let speed_info = if speed != 1.0 && dur > 0 {
+            let left = ((dur - pos) as f32 / speed).round() as u64;
+            format!("  {speed:.1}× -{:02}:{:02}", left / 60, left % 60)
+        } else if speed != 1.0 {
+            format!("  {speed:.1}×")
+        } else {
+            String::new()
+        };
+
         let info_line = Paragraph::new(format!(
-            "▶ {} – {} - {}  {:02}:{:02} / {:02}:{:02}",
+            "▶ {} – {} - {}  {:02}:{:02} / {:02}:{:02}{}  [{}]",
             track.album_artist,
             track.album,
             track.title,
             pos / 60, pos % 60,
             dur / 60, dur % 60,
+            speed_info,
+            volume,
         ))
         .style(Style::default().fg(Color::Gray));
 
+        // Track info and progress on the left, level meters on the right
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Min(1),
+                Constraint::Length(METER_WIDTH + 1),
+            ])
+            .split(area);
+
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
                 Constraint::Length(1),
                 Constraint::Length(1),
             ])
-            .split(area);
+            .split(columns[0]);
 
         let info_chunk = chunks[0];
         let gauge_chunk = chunks[1];
@@ -93,6 +142,21 @@ fn render_footer(f: &mut Frame, app: &App, area: Rect) {
             .ratio(percent);
 
         f.render_widget(gauge, gauge_chunk);
+        render_loop_region(f, app, gauge_chunk, dur);
+
+        let meters: Vec<Line> = ["L", "R"]
+            .into_iter()
+            .zip(&app.meters.channels)
+            .map(|(label, channel)| meter_line(label, channel))
+            .collect();
+
+        // Keep a column free between the gauge and the meters
+        let meter_area = Rect {
+            x: columns[1].x + 1,
+            width: columns[1].width.saturating_sub(1),
+            ..columns[1]
+        };
+        f.render_widget(Paragraph::new(meters), meter_area);
     } else {
         let empty = Paragraph::new("⏹ Nothing playing")
             .style(Style::default().fg(Color::DarkGray));
@@ -101,3 +165,83 @@ fn render_footer(f: &mut Frame, app: &App, area: Rect) {
     }
 }
 
+/// Tint the part of the progress gauge covered by the A-B loop, or just
+/// point A while B is still to be marked
+fn render_loop_region(f: &mut Frame, app: &App, gauge: Rect, dur: u64) {
+    if dur == 0 || gauge.width == 0 {
+        return;
+    }
+
+    let (a, b) = match (app.loop_region, app.loop_a) {
+        (Some((a, b)), _) => (a, b),
+        (None, Some(a)) => (a, a),
+        (None, None) => return,
+    };
+
+    let column = |time: Duration| {
+        let fraction = (time.as_secs_f64() / dur as f64).clamp(0.0, 1.0);
+        ((fraction * gauge.width as f64) as u16).min(gauge.width - 1)
+    };
+
+    let buf = f.buffer_mut();
+    for x in gauge.left() + column(a)..=gauge.left() + column(b) {
+        for y in gauge.top()..gauge.bottom() {
+            let cell = &mut buf[(x, y)];
+            if cell.symbol() == symbols::block::FULL {
+                cell.set_fg(Color::Cyan);
+            }
+            cell.set_bg(Color::Blue);
+        }
+    }
+}
+
+/// Cells of each level meter bar
+const METER_BAR: u16 = 16;
+
+/// Label, bar, peak readout and clip indicator
+const METER_WIDTH: u16 = 2 + METER_BAR + 6 + 5;
+
+/// One channel's meter, e.g. `L ██████████|···· -3.2 CLIP`. The bar shows
+/// RMS, the mark the held peak, both from `FLOOR_DB` to 0 dBFS.
+fn meter_line(label: &str, channel: &MeterChannel) -> Line<'static> {
+    let cell_of = |db: f32| ((db - FLOOR_DB) / -FLOOR_DB * METER_BAR as f32) as u16;
+    let rms_cells = cell_of(channel.rms_db);
+    let peak_cell = cell_of(channel.peak_db).min(METER_BAR - 1);
+
+    let mut spans = vec![Span::raw(format!("{label} "))];
+
+    for cell in 0..METER_BAR {
+        // Level at the right edge of this cell
+        let db = FLOOR_DB * (1.0 - (cell + 1) as f32 / METER_BAR as f32);
+        let colour = if db > -3.0 {
+            Color::Red
+        } else if db > -12.0 {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+
+        let span = if cell < rms_cells {
+            Span::styled("█", Style::default().fg(colour))
+        } else if cell == peak_cell && channel.peak_db > FLOOR_DB {
+            Span::styled("|", Style::default().fg(colour))
+        } else {
+            Span::styled("·", Style::default().fg(Color::DarkGray))
+        };
+        spans.push(span);
+    }
+
+    spans.push(Span::styled(
+        format!(" {:>5.1}", channel.peak_db),
+        Style::default().fg(Color::Gray),
+    ));
+
+    if channel.is_clipping() {
+        spans.push(Span::styled(
+            " CLIP",
+            Style::default().fg(Color::White).bg(Color::Red),
+        ));
+    }
+
+    Line::from(spans)
+}
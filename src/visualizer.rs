@@ -0,0 +1,359 @@
+use std::f32::consts::PI;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::player::OutputTap;
+
+/// Samples per analysis, a power of two; about 90 ms at 44.1 kHz
+const FFT_SIZE: usize = 4096;
+
+/// Frequency range shown, capped at half the output rate
+const MIN_FREQ: f32 = 30.0;
+const MAX_FREQ: f32 = 16000.0;
+
+/// Level drawn as an empty bar
+const FLOOR_DB: f32 = -72.0;
+
+/// How fast bars and released peaks sink, in full heights per second
+const BAR_FALL_PER_SEC: f32 = 1.5;
+const PEAK_FALL_PER_SEC: f32 = 0.4;
+
+/// How long a peak stays put before it starts to fall
+const PEAK_HOLD_SECS: f32 = 1.0;
+
+pub const MIN_BARS: usize = 8;
+pub const MAX_BARS: usize = 128;
+
+/// Bars added or removed per key press
+const BAR_STEP: usize = 8;
+
+/// How the spectrum is drawn, stored in `settings.json`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VisualizerSettings {
+    pub bars: usize,
+
+    /// Spread bars evenly over octaves rather than over hertz
+    pub log_scale: bool,
+
+    /// Mark each bar's recent maximum, which then slowly falls
+    pub peak_hold: bool,
+}
+
+impl Default for VisualizerSettings {
+    fn default() -> Self {
+        Self {
+            bars: 32,
+            log_scale: true,
+            peak_hold: true,
+        }
+    }
+}
+
+impl VisualizerSettings {
+    pub fn change_bars(&mut self, more: bool) {
+        self.bars = if more {
+            self.bars + BAR_STEP
+        } else {
+            self.bars.saturating_sub(BAR_STEP)
+        }
+        .clamp(MIN_BARS, MAX_BARS);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Peak {
+    level: f32,
+
+    /// Seconds since the peak was last pushed up
+    held: f32,
+}
+
+/// Bar spectrum of the audio leaving the player, for the visualizer screen
+pub struct SpectrumState {
+    fft: Fft,
+    window: Vec<f32>,
+    re: Vec<f32>,
+    im: Vec<f32>,
+
+    /// Bar heights and peak marks, 0.0 to 1.0
+    pub bars: Vec<f32>,
+    peaks: Vec<Peak>,
+
+    /// Highest frequency shown at the current output rate
+    max_freq: f32,
+
+    /// Tap position at the last analysis, to tell when playback stalls
+    last_written: usize,
+    updated: Instant,
+}
+
+impl SpectrumState {
+    pub fn new() -> Self {
+        // Hann window against leakage from one bin into the next
+        let window = (0..FFT_SIZE)
+            .map(|i| (PI * i as f32 / FFT_SIZE as f32).sin().powi(2))
+            .collect();
+
+        Self {
+            fft: Fft::new(FFT_SIZE),
+            window,
+            re: vec![0.0; FFT_SIZE],
+            im: vec![0.0; FFT_SIZE],
+            bars: Vec::new(),
+            peaks: Vec::new(),
+            max_freq: MAX_FREQ,
+            last_written: 0,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Peak marks, 0.0 to 1.0, one per bar
+    pub fn peaks(&self) -> impl Iterator<Item = f32> + '_ {
+        self.peaks.iter().map(|peak| peak.level)
+    }
+
+    /// Analyse whatever reached the tap since the last call and let the
+    /// bars and peaks fall by the time that passed
+    pub fn update(&mut self, tap: &OutputTap, settings: &VisualizerSettings) {
+        let now = Instant::now();
+        let dt = now.duration_since(self.updated).as_secs_f32();
+        self.updated = now;
+
+        let count = settings.bars.clamp(MIN_BARS, MAX_BARS);
+        if self.bars.len() != count {
+            self.bars = vec![0.0; count];
+            self.peaks = vec![Peak::default(); count];
+        }
+
+        // Nothing new means nothing is playing, so let everything sink
+        let written = tap.written();
+        let levels = if written != self.last_written {
+            self.last_written = written;
+            self.analyse(tap, count, settings.log_scale)
+        } else {
+            vec![0.0; count]
+        };
+
+        for ((bar, peak), level) in self.bars.iter_mut().zip(&mut self.peaks).zip(levels) {
+            *bar = level.max(*bar - BAR_FALL_PER_SEC * dt).max(0.0);
+
+            if !settings.peak_hold || *bar >= peak.level {
+                peak.level = *bar;
+                peak.held = 0.0;
+            } else {
+                peak.held += dt;
+                if peak.held > PEAK_HOLD_SECS {
+                    peak.level = (peak.level - PEAK_FALL_PER_SEC * dt).max(*bar);
+                }
+            }
+        }
+    }
+
+    /// Where `freq` sits along the bars, 0.0 to 1.0, if it is shown
+    pub fn position_of(&self, freq: f32, log_scale: bool) -> Option<f32> {
+        let position = if log_scale {
+            (freq / MIN_FREQ).ln() / (self.max_freq / MIN_FREQ).ln()
+        } else {
+            freq / self.max_freq
+        };
+
+        (0.0..=1.0).contains(&position).then_some(position)
+    }
+
+    /// Frequency at `position` along the bars, 0.0 to 1.0
+    fn frequency_at(&self, position: f32, log_scale: bool) -> f32 {
+        if log_scale {
+            MIN_FREQ * (self.max_freq / MIN_FREQ).powf(position)
+        } else {
+            self.max_freq * position
+        }
+    }
+
+    /// Level of each of `count` bands of the latest `FFT_SIZE` samples
+    fn analyse(&mut self, tap: &OutputTap, count: usize, log_scale: bool) -> Vec<f32> {
+        let rate = tap.sample_rate() as f32;
+        self.max_freq = MAX_FREQ.min(rate / 2.0);
+
+        tap.latest(&mut self.re);
+        for (sample, weight) in self.re.iter_mut().zip(&self.window) {
+            *sample *= weight;
+        }
+        self.im.fill(0.0);
+        self.fft.run(&mut self.re, &mut self.im);
+
+        // A full-scale sine comes out at 0 dB
+        let scale = 2.0 / self.window.iter().sum::<f32>();
+        let bin_hz = rate / FFT_SIZE as f32;
+        let last_bin = FFT_SIZE / 2 - 1;
+
+        (0..count)
+            .map(|bar| {
+                let low = self.frequency_at(bar as f32 / count as f32, log_scale);
+                let high = self.frequency_at((bar + 1) as f32 / count as f32, log_scale);
+
+                // Low bars may be narrower than a bin and share it with
+                // their neighbours
+                let first = ((low / bin_hz) as usize).min(last_bin);
+                let last = ((high / bin_hz).ceil() as usize).clamp(first + 1, last_bin + 1);
+
+                let magnitude = (first..last)
+                    .map(|bin| self.re[bin].hypot(self.im[bin]))
+                    .fold(0.0, f32::max)
+                    * scale;
+
+                let db = 20.0 * magnitude.max(1e-9).log10();
+                ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+/// In-place radix-2 FFT of a fixed power-of-two size
+struct Fft {
+    /// `e^(-2πik/n)` for the first half of the circle
+    twiddles: Vec<(f32, f32)>,
+}
+
+impl Fft {
+    fn new(size: usize) -> Self {
+        let twiddles = (0..size / 2)
+            .map(|k| {
+                let angle = -2.0 * std::f64::consts::PI * k as f64 / size as f64;
+                (angle.cos() as f32, angle.sin() as f32)
+            })
+            .collect();
+
+        Self { twiddles }
+    }
+
+    fn run(&self, re: &mut [f32], im: &mut [f32]) {
+        let n = re.len();
+
+        // Bit-reversed order, so the butterflies can work in place
+        let mut j = 0;
+        for i in 1..n {
+            let mut bit = n >> 1;
+            while j & bit != 0 {
+                j ^= bit;
+                bit >>= 1;
+            }
+            j |= bit;
+
+            if i < j {
+                re.swap(i, j);
+                im.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= n {
+            let half = len / 2;
+            let stride = n / len;
+
+            for start in (0..n).step_by(len) {
+                for k in 0..half {
+                    let (w_re, w_im) = self.twiddles[k * stride];
+                    let a = start + k;
+                    let b = a + half;
+
+                    let t_re = re[b] * w_re - im[b] * w_im;
+                    let t_im = re[b] * w_im + im[b] * w_re;
+
+                    re[b] = re[a] - t_re;
+                    im[b] = im[a] - t_im;
+                    re[a] += t_re;
+                    im[a] += t_im;
+                }
+            }
+
+            len *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+
+    /// A tap holding a full-scale mono sine at `bin` of the analysis
+    fn tap_with_bin(bin: usize) -> (OutputTap, f32) {
+        let freq = bin as f32 * RATE as f32 / FFT_SIZE as f32;
+        let tap = OutputTap::default();
+        tap.set_sample_rate(RATE);
+
+        let sine: Vec<f32> = (0..FFT_SIZE * 2)
+            .map(|i| {
+                (2.0 * std::f64::consts::PI * bin as f64 * i as f64 / FFT_SIZE as f64).sin() as f32
+            })
+            .collect();
+        tap.write(&sine, 1);
+
+        (tap, freq)
+    }
+
+    #[test]
+    fn fft_of_a_sine_is_one_bin() {
+        let fft = Fft::new(64);
+        let mut re: Vec<f32> = (0..64)
+            .map(|i| (2.0 * PI * 5.0 * i as f32 / 64.0).cos())
+            .collect();
+        let mut im = vec![0.0; 64];
+        fft.run(&mut re, &mut im);
+
+        for bin in 0..64 {
+            let magnitude = re[bin].hypot(im[bin]);
+            let expected = if bin == 5 || bin == 59 { 32.0 } else { 0.0 };
+            assert!(
+                (magnitude - expected).abs() < 1e-3,
+                "bin {bin}: {magnitude}"
+            );
+        }
+    }
+
+    #[test]
+    fn full_scale_sine_reads_0_db_in_its_bar() {
+        for log_scale in [false, true] {
+            let (tap, freq) = tap_with_bin(200);
+            let mut spectrum = SpectrumState::new();
+            let count = 32;
+
+            let levels = spectrum.analyse(&tap, count, log_scale);
+            let position = spectrum.position_of(freq, log_scale).unwrap();
+            let bar = (position * count as f32) as usize;
+
+            // Level 1.0 is 0 dB
+            assert!(levels[bar] > 0.99, "bar {bar}: {}", levels[bar]);
+
+            for (i, level) in levels.iter().enumerate() {
+                if i.abs_diff(bar) > 2 {
+                    assert!(*level < 0.05, "bar {i}: {level}, log scale {log_scale}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bar_mappings_are_inverses() {
+        let spectrum = SpectrumState::new();
+
+        for log_scale in [false, true] {
+            for i in 0..=20 {
+                let position = i as f32 / 20.0;
+                let freq = spectrum.frequency_at(position, log_scale);
+                let back = spectrum.position_of(freq, log_scale).unwrap();
+
+                assert!(
+                    (back - position).abs() < 1e-4,
+                    "{position} -> {freq} Hz -> {back}"
+                );
+            }
+        }
+
+        assert_eq!(spectrum.position_of(MAX_FREQ * 2.0, false), None);
+        assert_eq!(spectrum.position_of(MIN_FREQ / 2.0, true), None);
+    }
+}